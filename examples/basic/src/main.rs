@@ -1,11 +1,30 @@
+use futures::StreamExt;
 use std::io::Error;
 use std::str::from_utf8;
 use std::time::SystemTime;
 use tokio::time::{sleep, Duration};
 use waku::{
-    waku_destroy, waku_new, Encoding, Event, WakuContentTopic, WakuMessage, WakuNodeConfig, LibwakuResponse,
+    waku_destroy, waku_new, Encoding, Event, WakuContentTopic, WakuEventStream, WakuMessage,
+    WakuNodeConfig,
 };
 
+async fn print_messages(name: &'static str, mut events: WakuEventStream) {
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(Event::WakuMessage(evt)) => {
+                println!("WakuMessage event received: {:?}", evt.waku_message);
+                let payload = evt.waku_message.payload.to_vec();
+                let msg = from_utf8(&payload).unwrap_or("<non utf8 payload>");
+                println!("::::::::::::::::::::::::::::::::::::::::::::::::::::");
+                println!("Message Received in {}: {}", name, msg);
+                println!("::::::::::::::::::::::::::::::::::::::::::::::::::::");
+            }
+            Ok(other) => println!("Other event received in {}: {:?}", name, other),
+            Err(err) => eprintln!("Could not decode event in {}: {}", name, err),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let node1 = waku_new(Some(WakuNodeConfig {
@@ -20,52 +39,15 @@ async fn main() -> Result<(), Error> {
     }))
     .expect("should instantiate");
 
-    let node1 = node1.start().expect("node1 should start");
-    let node2 = node2.start().expect("node2 should start");
+    let node1 = node1.start().await.expect("node1 should start");
+    let node2 = node2.start().await.expect("node2 should start");
 
     // ========================================================================
-    // Setting an event callback to be executed each time a message is received
-    node2.set_event_callback(&|response| {
-        if let LibwakuResponse::Success(v) = response {
-            let event: Event =
-                serde_json::from_str(v.unwrap().as_str()).expect("Parsing event to succeed");
-
-            match event {
-                Event::WakuMessage(evt) => {
-                    println!("WakuMessage event received: {:?}", evt.waku_message);
-                    let message = evt.waku_message;
-                    let payload = message.payload.to_vec();
-                    let msg = from_utf8(&payload).expect("should be valid message");
-                    println!("::::::::::::::::::::::::::::::::::::::::::::::::::::");
-                    println!("Message Received in NODE 2: {}", msg);
-                    println!("::::::::::::::::::::::::::::::::::::::::::::::::::::");
-                }
-                Event::Unrecognized(err) => panic!("Unrecognized waku event: {:?}", err),
-                _ => panic!("event case not expected"),
-            };
-        }
-    });
-
-    node1.set_event_callback(&|response| {
-        if let LibwakuResponse::Success(v) = response {
-            let event: Event =
-                serde_json::from_str(v.unwrap().as_str()).expect("Parsing event to succeed");
-
-            match event {
-                Event::WakuMessage(evt) => {
-                    println!("WakuMessage event received: {:?}", evt.waku_message);
-                    let message = evt.waku_message;
-                    let payload = message.payload.to_vec();
-                    let msg = from_utf8(&payload).expect("should be valid message");
-                    println!("::::::::::::::::::::::::::::::::::::::::::::::::::::");
-                    println!("Message Received in NODE 1: {}", msg);
-                    println!("::::::::::::::::::::::::::::::::::::::::::::::::::::");
-                }
-                Event::Unrecognized(err) => panic!("Unrecognized waku event: {:?}", err),
-                _ => panic!("event case not expected"),
-            };
-        }
-    });
+    // Consuming each node event stream in its own task
+
+    for (name, events) in [("NODE 1", node1.events()), ("NODE 2", node2.events())] {
+        tokio::spawn(print_messages(name, events));
+    }
 
     // ========================================================================
     // Subscribe to pubsub topic
@@ -73,10 +55,12 @@ async fn main() -> Result<(), Error> {
 
     node1
         .relay_subscribe(&topic)
+        .await
         .expect("node1 should subscribe");
 
     node2
         .relay_subscribe(&topic)
+        .await
         .expect("node2 should subscribe");
 
     // ========================================================================
@@ -84,10 +68,12 @@ async fn main() -> Result<(), Error> {
 
     let addresses2 = node2
         .listen_addresses()
+        .await
         .expect("should obtain the addresses");
 
     node1
-        .connect(&addresses2[0], None)
+        .connect(&addresses2[0], None, None)
+        .await
         .expect("node1 should connect to node2");
 
     // ========================================================================
@@ -114,6 +100,7 @@ async fn main() -> Result<(), Error> {
     );
     node1
         .relay_publish_message(&message, &topic, None)
+        .await
         .expect("should have sent the message");
 
     // ========================================================================
@@ -124,13 +111,13 @@ async fn main() -> Result<(), Error> {
     // ========================================================================
     // Stop both instances
 
-    let node1 = node1.stop().expect("should stop");
-    let node2 = node2.stop().expect("should stop");
+    let node1 = node1.stop().await.expect("should stop");
+    let node2 = node2.stop().await.expect("should stop");
 
     // ========================================================================
     // Free resources
-    waku_destroy(node1).expect("should deallocate");
-    waku_destroy(node2).expect("should deallocate");
+    waku_destroy(node1).await.expect("should deallocate");
+    waku_destroy(node2).await.expect("should deallocate");
 
     Ok(())
 }
@@ -2,7 +2,7 @@ use tokio::signal;
 use std::str::FromStr;
 
 use waku::{
-    waku_new, Encoding, Event, Initialized, LibwakuResponse, Multiaddr, Running, WakuContentTopic,
+    waku_new, Encoding, Event, Initialized, LibwakuResponse, LogLevel, Multiaddr, Running, WakuContentTopic,
     WakuMessage, WakuNodeConfig, WakuNodeContext, WakuNodeHandle,
 };
 
@@ -17,13 +17,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // node_key: Some(SecretKey::from_str("2fc0515879e52b7b73297cfd6ab3abf7c344ef84b7a90ff6f4cc19e05a198027").unwrap()),
         max_message_size: Some("1024KiB".to_string()),
         relay_topics: vec![topic.to_string()],
-        log_level: Some("DEBUG"), // Supported: TRACE, DEBUG, INFO, NOTICE, WARN, ERROR or FATAL
+        log_level: Some(LogLevel::Debug),
 
         keep_alive: Some(true),
 
         // Discovery
         dns_discovery: Some(true),
-        dns_discovery_url: Some("enrtree://AMOJVZX4V6EXP7NTJPMAYJYST2QP6AJXYW76IU6VGJS7UVSNDYZG4@boot.prod.status.nodes.status.im"),
+        dns_discovery_url: Some("enrtree://AMOJVZX4V6EXP7NTJPMAYJYST2QP6AJXYW76IU6VGJS7UVSNDYZG4@boot.prod.status.nodes.status.im".to_string()),
         discv5_discovery: Some(true),
         discv5_udp_port: Some(9000),
         discv5_enr_auto_update: Some(false),
@@ -32,12 +32,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }))
     .expect("should instantiate");
     
-    if let Ok(_) = waku.start() {
-        println!("It works!");
-    }
-    else {
-        println!("It didn't work. Let's go home xD");
-    }
+    let waku = waku.start().await.expect("waku should start");
+    println!("It works!");
 
     let my_closure = move |response| {
         if let LibwakuResponse::Success(v) = response {
@@ -55,15 +51,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     // Establish a closure that handles the incoming messages
-    waku.ctx.waku_set_event_callback(my_closure);
-    waku.relay_subscribe(&topic.to_string()).expect("waku should subscribe");
+    waku.set_event_callback(my_closure);
+    waku.relay_subscribe(&topic.to_string()).await.expect("waku should subscribe");
     
     // Wait for Ctrl+C (SIGINT) signal
     signal::ctrl_c().await.expect("Failed to listen for Ctrl+C signal");
 
     println!("Ctrl+C pressed, shutting down.");
 
-    waku.stop().expect("Failed to stop Waku");
+    waku.stop().await.expect("Failed to stop Waku");
 
     signal::ctrl_c().await?;
     println!("ctrl-c received!");
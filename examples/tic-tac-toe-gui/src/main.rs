@@ -11,7 +11,7 @@ use std::time::{Duration, Instant};
 
 use tokio::sync::mpsc;
 use waku::{
-    waku_new, Encoding, Event, Initialized, LibwakuResponse, Multiaddr, Running, WakuContentTopic,
+    waku_new, Encoding, Event, Initialized, LibwakuResponse, LogLevel, Multiaddr, Running, WakuContentTopic,
     WakuMessage, WakuNodeConfig, WakuNodeContext, WakuNodeHandle,
 };
 
@@ -37,7 +37,7 @@ struct MoveMessage {
 
 struct TicTacToeApp {
     game_state: Arc<Mutex<GameState>>,
-    waku: WakuNodeHandle,
+    waku: WakuNodeHandle<Running>,
     game_topic: &'static str,
     tx: mpsc::Sender<String>, // Sender to send `msg` to main thread
     player_role: Option<Player>, // Store the player's role (X or O)
@@ -45,7 +45,7 @@ struct TicTacToeApp {
 
 impl TicTacToeApp {
     fn new(
-        waku: WakuNodeHandle,
+        waku: WakuNodeHandle<Running>,
         game_topic: &'static str,
         game_state: Arc<Mutex<GameState>>,
         tx: mpsc::Sender<String>,
@@ -59,10 +59,7 @@ impl TicTacToeApp {
         }
     }
 
-    fn start(&mut self) {
-        // Start the waku node
-        self.waku.start().expect("waku should start");
-
+    async fn start(&mut self) {
         let tx_clone = self.tx.clone();
 
         let my_closure = move |response| {
@@ -95,10 +92,10 @@ impl TicTacToeApp {
         };
 
         // Establish a closure that handles the incoming messages
-        self.waku.ctx.waku_set_event_callback(my_closure);
+        self.waku.set_event_callback(my_closure);
 
         // Subscribe to desired topic
-        self.waku.relay_subscribe(&self.game_topic.to_string()).expect("waku should subscribe");
+        self.waku.relay_subscribe(&self.game_topic.to_string()).await.expect("waku should subscribe");
 
         // Connect to hard-coded node
         // let target_node_multi_addr =
@@ -107,7 +104,7 @@ impl TicTacToeApp {
         //     // "/ip4/24.144.78.119/tcp/30303/p2p/16Uiu2HAm3xVDaz6SRJ6kErwC21zBJEZjavVXg7VSkoWzaV1aMA3F"
         //     .parse::<Multiaddr>().expect("parse multiaddress");
 
-        // self.waku.connect(&target_node_multi_addr, None)
+        // self.waku.connect(&target_node_multi_addr, None, None)
         //      .expect("waku should connect to other node");
     }
 
@@ -130,8 +127,13 @@ impl TicTacToeApp {
         );
 
         // let waku_handle = self.waku.lock().unwrap();
-        self.waku.relay_publish_message(&message, &self.game_topic.to_string(), None)
-            .expect("Failed to send message");
+        let waku = self.waku.clone();
+        let game_topic = self.game_topic;
+        tokio::spawn(async move {
+            waku.relay_publish_message(&message, game_topic, None)
+                .await
+                .expect("Failed to send message");
+        });
     }
 
     fn make_move(&mut self, row: usize, col: usize) {
@@ -312,13 +314,13 @@ async fn main() -> eframe::Result<()> {
         // node_key: Some(SecretKey::from_str("2fc0515879e52b7b73297cfd6ab3abf7c344ef84b7a90ff6f4cc19e05a198027").unwrap()),
         max_message_size: Some("1024KiB".to_string()),
         relay_topics: vec![game_topic.to_string()],
-        log_level: Some("DEBUG"), // Supported: TRACE, DEBUG, INFO, NOTICE, WARN, ERROR or FATAL
+        log_level: Some(LogLevel::Debug),
 
         keep_alive: Some(true),
 
         // Discovery
         dns_discovery: Some(true),
-        dns_discovery_url: Some("enrtree://AMOJVZX4V6EXP7NTJPMAYJYST2QP6AJXYW76IU6VGJS7UVSNDYZG4@boot.prod.status.nodes.status.im"),
+        dns_discovery_url: Some("enrtree://AMOJVZX4V6EXP7NTJPMAYJYST2QP6AJXYW76IU6VGJS7UVSNDYZG4@boot.prod.status.nodes.status.im".to_string()),
         discv5_discovery: Some(true),
         discv5_udp_port: Some(9000),
         discv5_enr_auto_update: Some(false),
@@ -326,7 +328,9 @@ async fn main() -> eframe::Result<()> {
         ..Default::default()
     }))
     .expect("should instantiate");
-    // Initialize Waku
+
+    // Start the waku node
+    let waku = waku.start().await.expect("waku should start");
 
     let game_state = GameState {
         board: [[None; 3]; 3],
@@ -338,7 +342,7 @@ async fn main() -> eframe::Result<()> {
     let clone = shared_state.clone();
     let mut app = TicTacToeApp::new(waku, game_topic, clone, tx);
 
-    app.start();
+    app.start().await;
 
     let clone = shared_state.clone();
     // Listen for messages in the main thread
@@ -17,7 +17,7 @@ use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 use waku::{
     waku_new, Event, WakuNodeConfig,
-    LibwakuResponse, Multiaddr, Running, WakuNodeHandle,
+    LibwakuResponse, LogLevel, Multiaddr, Running, WakuNodeHandle,
 };
 
 fn greeting() {
@@ -196,7 +196,7 @@ async fn main() {
     let waku = waku_new(Some(WakuNodeConfig {
         port: Some(60010),
         cluster_id: Some(16),
-        log_level: Some("ERROR"), // Supported: TRACE, DEBUG, INFO, NOTICE, WARN, ERROR or FATAL
+        log_level: Some(LogLevel::Error),
         ..Default::default()
     }))
     .expect("should instantiate");
@@ -205,7 +205,7 @@ async fn main() {
     //     println!("Ctrl+C detected. Exiting gracefully...");
     //     // waku.stop();
     // }).expect("Error setting Ctrl+C handler");
-    let waku = waku.start().expect("waku should start");
+    let waku = waku.start().await.expect("waku should start");
     // let tx_cloned = tx.clone();
 
     // Wait for tasks to complete
@@ -249,7 +249,7 @@ async fn main() {
         }
     });
 
-    waku.relay_subscribe(&topic).expect("waku should subscribe");
+    waku.relay_subscribe(&topic).await.expect("waku should subscribe");
 
     // let target_node_multi_addr =
     //   "/dns4/store-01.do-ams3.status.staging.status.im/tcp/30303/p2p/16Uiu2HAm3xVDaz6SRJ6kErwC21zBJEZjavVXg7VSkoWzaV1aMA3F"
@@ -258,7 +258,7 @@ async fn main() {
       "/ip4/24.144.78.119/tcp/30303/p2p/16Uiu2HAm3xVDaz6SRJ6kErwC21zBJEZjavVXg7VSkoWzaV1aMA3F"
       .parse::<Multiaddr>().expect("parse multiaddress");
 
-    waku.connect(&target_node_multi_addr, None)
+    waku.connect(&target_node_multi_addr, None, None).await
         .expect("waku should connect to other node");
 
     // Welcome the player
@@ -275,7 +275,7 @@ async fn main() {
     let _ = waku.relay_publish_txt(&topic,
         &board_string,
         "tic-tac-toe-example",
-        None);
+        None).await;
     println!("AAAA 3");
 
     // Main receiver task
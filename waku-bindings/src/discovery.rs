@@ -0,0 +1,421 @@
+//! [EIP-1459](https://eips.ethereum.org/EIPS/eip-1459) node lists, published as DNS TXT records
+//! and resolved through [`crate::WakuNodeConfig::dns_discovery_url`] or [`crate::WakuNodeHandle::dns_discovery`]
+
+// std
+use std::collections::BTreeMap;
+// crates
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use data_encoding::BASE32_NOPAD;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use sha3::{Digest, Keccak256};
+// internal
+use crate::general::{Result, WakuError};
+use crate::node::Enr;
+
+const ROOT_PREFIX: &str = "enrtree-root:v1";
+const BRANCH_PREFIX: &str = "enrtree-branch:";
+const LINK_PREFIX: &str = "enrtree://";
+const ENR_PREFIX: &str = "enr:";
+/// Most children a branch holds, keeping its TXT record within a single DNS response as go-ethereum does
+const MAX_CHILDREN: usize = 13;
+
+/// Node list published as a tree of DNS TXT records: signed root, branches, and leaves holding
+/// the node records plus links to other trees
+#[derive(Clone, Debug, Default)]
+pub struct EnrTree {
+    sequence: u32,
+    enrs: Vec<Enr>,
+    links: Vec<String>,
+}
+
+/// TXT records of a signed [`EnrTree`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnrTreeRecords {
+    /// `enrtree://<public key>@<domain>` url of the tree, as expected by [`crate::WakuNodeConfig::dns_discovery_url`]
+    pub url: String,
+    /// TXT record content by domain name: the root at `<domain>`, branches and leaves at `<hash>.<domain>`
+    pub records: BTreeMap<String, String>,
+}
+
+impl EnrTree {
+    /// Tree listing `enrs`, with sequence number `1` and no links
+    pub fn new<I: IntoIterator<Item = Enr>>(enrs: I) -> Self {
+        Self {
+            sequence: 1,
+            enrs: enrs.into_iter().collect(),
+            links: Vec::new(),
+        }
+    }
+
+    /// Sequence number of the tree, to be increased on every update so clients pick it up
+    pub fn with_sequence(mut self, sequence: u32) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Link other trees, given by their `enrtree://` url, from this one
+    pub fn with_links<I: IntoIterator<Item = S>, S: Into<String>>(mut self, links: I) -> Self {
+        self.links = links.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sequence number of the tree
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Node records listed in the tree
+    pub fn enrs(&self) -> &[Enr] {
+        &self.enrs
+    }
+
+    /// `enrtree://` urls of the linked trees
+    pub fn links(&self) -> &[String] {
+        &self.links
+    }
+
+    /// TXT records publishing the tree under `domain`, signed with `key`.
+    /// Node records are sorted by node id, so the same list always gives the same records.
+    pub fn sign(&self, domain: &str, key: &SecretKey) -> Result<EnrTreeRecords> {
+        for link in &self.links {
+            parse_url(link)?;
+        }
+        let mut enrs: Vec<&Enr> = self.enrs.iter().collect();
+        enrs.sort_by_key(|enr| enr.node_id().raw());
+
+        let mut entries = BTreeMap::new();
+        let enr_root = build_subtree(
+            enrs.iter().map(|enr| enr.to_base64()).collect(),
+            &mut entries,
+        );
+        let link_root = build_subtree(self.links.clone(), &mut entries);
+        let root = format!(
+            "{ROOT_PREFIX} e={} l={} seq={}",
+            insert_entry(enr_root, &mut entries),
+            insert_entry(link_root, &mut entries),
+            self.sequence
+        );
+
+        let secp = Secp256k1::signing_only();
+        let (recovery_id, signature) = secp
+            .sign_ecdsa_recoverable(&root_message(&root), key)
+            .serialize_compact();
+        let mut signature = signature.to_vec();
+        signature.push(recovery_id.to_i32() as u8);
+
+        let mut records = BTreeMap::from([(
+            domain.to_string(),
+            format!("{root} sig={}", URL_SAFE_NO_PAD.encode(signature)),
+        )]);
+        records.extend(
+            entries
+                .into_iter()
+                .map(|(hash, entry)| (format!("{hash}.{domain}"), entry)),
+        );
+        Ok(EnrTreeRecords {
+            url: tree_url(domain, &key.public_key(&secp)),
+            records,
+        })
+    }
+
+    /// Read the tree published as `records`, checking the root signature against
+    /// the key of the url and the hash of every entry
+    pub fn verify(records: &EnrTreeRecords) -> Result<Self> {
+        let (public_key, domain) = parse_url(&records.url)?;
+        let root = lookup(records, &domain)?;
+        let (content, signature) = root
+            .rsplit_once(" sig=")
+            .ok_or_else(|| invalid(format!("root record without signature: {root}")))?;
+
+        let mut fields = content
+            .strip_prefix(ROOT_PREFIX)
+            .ok_or_else(|| invalid(format!("unknown root record: {root}")))?
+            .split_whitespace()
+            .map(|field| field.split_once('=').unwrap_or((field, "")))
+            .collect::<BTreeMap<_, _>>();
+        let mut field = |name: &str| {
+            fields
+                .remove(name)
+                .ok_or_else(|| invalid(format!("root record without `{name}`: {root}")))
+        };
+        let (enr_root, link_root) = (field("e")?, field("l")?);
+        let sequence = field("seq")?
+            .parse()
+            .map_err(|e| invalid(format!("invalid root sequence number: {e}")))?;
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature.trim_end_matches('='))
+            .map_err(|e| invalid(format!("invalid root signature encoding: {e}")))?;
+        let signature = signature
+            .get(..64)
+            .and_then(|compact| Signature::from_compact(compact).ok())
+            .ok_or_else(|| invalid("invalid root signature".to_string()))?;
+        Secp256k1::verification_only()
+            .verify_ecdsa(&root_message(content), &signature, &public_key)
+            .map_err(|_| invalid(format!("root is not signed by the key of {}", records.url)))?;
+
+        let mut enrs = Vec::new();
+        for leaf in resolve(records, &domain, enr_root)? {
+            let enr = leaf
+                .strip_prefix(ENR_PREFIX)
+                .and(leaf.parse().ok())
+                .ok_or_else(|| invalid(format!("invalid node record: {leaf}")))?;
+            enrs.push(enr);
+        }
+        let links = resolve(records, &domain, link_root)?;
+        for link in &links {
+            parse_url(link)?;
+        }
+
+        Ok(Self {
+            sequence,
+            enrs,
+            links,
+        })
+    }
+}
+
+fn invalid(msg: String) -> WakuError {
+    WakuError::InvalidEnrTree(msg)
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Name of the subdomain holding `entry`
+fn entry_hash(entry: &str) -> String {
+    BASE32_NOPAD.encode(&keccak256(entry.as_bytes())[..16])
+}
+
+fn root_message(root: &str) -> Message {
+    Message::from_slice(&keccak256(root.as_bytes())).expect("keccak256 digests are 32 bytes long")
+}
+
+fn insert_entry(entry: String, entries: &mut BTreeMap<String, String>) -> String {
+    let hash = entry_hash(&entry);
+    entries.insert(hash.clone(), entry);
+    hash
+}
+
+/// Root entry of the subtree holding `leaves`, its descendants being added to `entries`
+fn build_subtree(leaves: Vec<String>, entries: &mut BTreeMap<String, String>) -> String {
+    match leaves.len() {
+        1 => leaves.into_iter().next().expect("there is one leaf"),
+        n if n <= MAX_CHILDREN => {
+            let hashes: Vec<String> = leaves
+                .into_iter()
+                .map(|leaf| insert_entry(leaf, entries))
+                .collect();
+            format!("{BRANCH_PREFIX}{}", hashes.join(","))
+        }
+        _ => {
+            let subtrees = leaves
+                .chunks(MAX_CHILDREN)
+                .map(|chunk| {
+                    let subtree = build_subtree(chunk.to_vec(), entries);
+                    insert_entry(subtree.clone(), entries);
+                    subtree
+                })
+                .collect();
+            build_subtree(subtrees, entries)
+        }
+    }
+}
+
+/// Leaves below the entry stored at `<hash>.<domain>`, in order
+fn resolve(records: &EnrTreeRecords, domain: &str, hash: &str) -> Result<Vec<String>> {
+    let entry = lookup(records, &format!("{hash}.{domain}"))?;
+    if !entry_hash(entry).eq_ignore_ascii_case(hash) {
+        return Err(invalid(format!("entry {hash} does not match its hash")));
+    }
+    match entry.strip_prefix(BRANCH_PREFIX) {
+        Some(children) => {
+            let mut leaves = Vec::new();
+            for child in children.split(',').filter(|child| !child.is_empty()) {
+                leaves.extend(resolve(records, domain, child)?);
+            }
+            Ok(leaves)
+        }
+        None => Ok(vec![entry.to_string()]),
+    }
+}
+
+/// TXT record of `name`, DNS names being case insensitive
+fn lookup<'a>(records: &'a EnrTreeRecords, name: &str) -> Result<&'a str> {
+    records
+        .records
+        .iter()
+        .find(|(record, _)| record.trim_end_matches('.').eq_ignore_ascii_case(name))
+        .map(|(_, content)| content.as_str())
+        .ok_or_else(|| invalid(format!("missing record {name}")))
+}
+
+fn tree_url(domain: &str, public_key: &PublicKey) -> String {
+    format!(
+        "{LINK_PREFIX}{}@{domain}",
+        BASE32_NOPAD.encode(&public_key.serialize())
+    )
+}
+
+/// Signing key and domain of an `enrtree://<public key>@<domain>` url
+fn parse_url(url: &str) -> Result<(PublicKey, String)> {
+    let (key, domain) = url
+        .strip_prefix(LINK_PREFIX)
+        .and_then(|tree| tree.split_once('@'))
+        .filter(|(_, domain)| !domain.is_empty())
+        .ok_or_else(|| {
+            invalid(format!(
+                "expected enrtree://<public key>@<domain>, got {url}"
+            ))
+        })?;
+    let public_key = BASE32_NOPAD
+        .decode(key.as_bytes())
+        .ok()
+        .and_then(|key| PublicKey::from_slice(&key).ok())
+        .ok_or_else(|| invalid(format!("invalid public key in {url}")))?;
+    Ok((public_key, domain.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entry_hash, parse_url, EnrTree, EnrTreeRecords};
+    use crate::{Enr, SecretKey, WakuError};
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    /// Example tree of [EIP-1459](https://eips.ethereum.org/EIPS/eip-1459#dns-record-structure)
+    const EIP_RECORDS: [(&str, &str); 6] = [
+        ("mynodes.org", "enrtree-root:v1 e=JWXYDBPXYWG6FX3GMDIBFA6CJ4 l=C7HRFPF3BLGF3YR4DY5KX3SMBE seq=1 sig=o908WmNp7LibOfPsr4btQwatZJ5URBr2ZAuxvK4UWHlsB9sUOTJQaGAlLPVAhM__XJesCHxLISo94z5Z2a463gA"),
+        ("C7HRFPF3BLGF3YR4DY5KX3SMBE.mynodes.org", "enrtree://AM5FCQLWIZX2QFPNJAP7VUERCCRNGRHWZG3YYHIUV7BVDQ5FDPRT2@morenodes.example.org"),
+        ("JWXYDBPXYWG6FX3GMDIBFA6CJ4.mynodes.org", "enrtree-branch:2XS2367YHAXJFGLZHVAWLQD4ZY,H4FHT4B454P6UXFD7JCYQ5PWDY,MHTDO6TMUBRIA2XWG5LUDACK24"),
+        ("2XS2367YHAXJFGLZHVAWLQD4ZY.mynodes.org", "enr:-HW4QOFzoVLaFJnNhbgMoDXPnOvcdVuj7pDpqRvh6BRDO68aVi5ZcjB3vzQRZH2IcLBGHzo8uUN3snqmgTiE56CH3AMBgmlkgnY0iXNlY3AyNTZrMaECC2_24YYkYHEgdzxlSNKQEnHhuNAbNlMlWJxrJxbAFvA"),
+        ("H4FHT4B454P6UXFD7JCYQ5PWDY.mynodes.org", "enr:-HW4QAggRauloj2SDLtIHN1XBkvhFZ1vtf1raYQp9TBW2RD5EEawDzbtSmlXUfnaHcvwOizhVYLtr7e6vw7NAf6mTuoCgmlkgnY0iXNlY3AyNTZrMaECjrXI8TLNXU0f8cthpAMxEshUyQlK-AM0PW2wfrnacNI"),
+        ("MHTDO6TMUBRIA2XWG5LUDACK24.mynodes.org", "enr:-HW4QLAYqmrwllBEnzWWs7I5Ev2IAs7x_dZlbYdRdMUx5EyKHDXp7AV5CkuPGUPdvbv1_Ms1CPfhcGCvSElSosZmyoqAgmlkgnY0iXNlY3AyNTZrMaECriawHKWdDRk2xeZkrOXBQ0dfMFLHY4eENZwdufn1S1o"),
+    ];
+    /// Key the EIP-1459 example tree is signed with, as used by go-ethereum's tests
+    const EIP_URL: &str =
+        "enrtree://AKPYQIUQIL7PSIACI32J7FGZW56E5FKHEFCCOFHILBIMW3M6LWXS2@mynodes.org";
+
+    fn eip_records() -> EnrTreeRecords {
+        EnrTreeRecords {
+            url: EIP_URL.to_string(),
+            records: EIP_RECORDS
+                .iter()
+                .map(|(name, content)| (name.to_string(), content.to_string()))
+                .collect(),
+        }
+    }
+
+    fn secret_key() -> SecretKey {
+        SecretKey::from_str("05f381866cc21f6c1e2e80e07fa732008e36d942dce3206ad6dcd6793c98d609")
+            .unwrap()
+    }
+
+    #[test]
+    fn verify_eip_tree() {
+        for (name, content) in &EIP_RECORDS[1..] {
+            assert!(name.starts_with(&entry_hash(content)));
+        }
+
+        let tree = EnrTree::verify(&eip_records()).unwrap();
+        assert_eq!(tree.sequence(), 1);
+        assert_eq!(tree.enrs().len(), 3);
+        assert_eq!(tree.links(), [EIP_RECORDS[1].1]);
+    }
+
+    #[test]
+    fn rebuild_eip_tree() {
+        let tree = EnrTree::verify(&eip_records()).unwrap();
+        let rebuilt = EnrTree::new(tree.enrs().iter().rev().cloned())
+            .with_links(tree.links().iter().cloned())
+            .sign("mynodes.org", &secret_key())
+            .unwrap();
+
+        // Entries do not depend on the signing key nor on the order of the records
+        let eip = eip_records();
+        assert_eq!(
+            rebuilt.records.keys().collect::<Vec<_>>(),
+            eip.records.keys().collect::<Vec<_>>()
+        );
+        for (name, content) in &eip.records {
+            if name != "mynodes.org" {
+                assert_eq!(&rebuilt.records[name], content);
+            }
+        }
+        assert!(rebuilt.records["mynodes.org"].starts_with(
+            "enrtree-root:v1 e=JWXYDBPXYWG6FX3GMDIBFA6CJ4 l=C7HRFPF3BLGF3YR4DY5KX3SMBE seq=1 sig="
+        ));
+    }
+
+    #[test]
+    fn sign_and_verify_large_tree() {
+        let enrs: Vec<Enr> = (0..30)
+            .map(|_| {
+                let key = enr::secp256k1::SecretKey::new(&mut rand::thread_rng());
+                enr::EnrBuilder::new("v4").build(&key).unwrap()
+            })
+            .collect();
+        let records = EnrTree::new(enrs.clone())
+            .with_sequence(7)
+            .sign("nodes.example.org", &secret_key())
+            .unwrap();
+        assert!(records.url.starts_with("enrtree://"));
+        assert!(records.url.ends_with("@nodes.example.org"));
+        assert!(records.records.values().all(|content| content.len() <= 370));
+
+        let tree = EnrTree::verify(&records).unwrap();
+        assert_eq!(tree.sequence(), 7);
+        let mut node_ids: Vec<_> = enrs.iter().map(|enr| enr.node_id().raw()).collect();
+        node_ids.sort();
+        assert_eq!(
+            tree.enrs()
+                .iter()
+                .map(|enr| enr.node_id())
+                .collect::<Vec<_>>(),
+            node_ids
+        );
+        assert!(tree.links().is_empty());
+    }
+
+    #[test]
+    fn reject_tampered_trees() {
+        let other_key =
+            SecretKey::from_str("2fc0515879e52b7b73297cfd6ab3abf7c344ef84b7a90ff6f4cc19e05a198027")
+                .unwrap();
+        let mut records = eip_records();
+        records.url = EnrTree::new([])
+            .sign("mynodes.org", &other_key)
+            .unwrap()
+            .url;
+        assert!(matches!(
+            EnrTree::verify(&records),
+            Err(WakuError::InvalidEnrTree(msg)) if msg.contains("not signed")
+        ));
+
+        let mut records = eip_records();
+        records.records.insert(
+            "MHTDO6TMUBRIA2XWG5LUDACK24.mynodes.org".to_string(),
+            EIP_RECORDS[3].1.to_string(),
+        );
+        assert!(matches!(
+            EnrTree::verify(&records),
+            Err(WakuError::InvalidEnrTree(msg)) if msg.contains("hash")
+        ));
+
+        let mut records = eip_records();
+        records.records =
+            BTreeMap::from([(EIP_RECORDS[0].0.to_string(), EIP_RECORDS[0].1.to_string())]);
+        assert!(EnrTree::verify(&records).is_err());
+
+        assert!(
+            parse_url("enrtree://AM5FCQLWIZX2QFPNJAP7VUERCCRNGRHWZG3YYHIUV7BVDQ5FDPRT2").is_err()
+        );
+        assert!(EnrTree::new([])
+            .with_links(["https://nodes.example.org"])
+            .sign("mynodes.org", &secret_key())
+            .is_err());
+    }
+}
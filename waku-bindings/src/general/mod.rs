@@ -14,8 +14,68 @@ pub type WakuMessageVersion = usize;
 /// Waku message hash, hex encoded sha256 digest of the message
 pub type MessageHash = String;
 
-/// Waku response, just a `Result` with an `String` error.
-pub type Result<T> = std::result::Result<T, String>;
+/// Waku response, a `Result` with a [`WakuError`] error.
+pub type Result<T> = std::result::Result<T, WakuError>;
+
+/// Errors returned by the Waku node and the types around it
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WakuError {
+    /// libwaku reported a failure, carrying its raw message
+    Libwaku(String),
+    /// A libwaku response or event could not be decoded
+    Decode(String),
+    /// The node configuration was rejected before reaching libwaku
+    InvalidConfig(String),
+    /// A pubsub or content topic is malformed
+    InvalidTopic(String),
+    /// A libp2p peer id is malformed
+    InvalidPeerId(String),
+    /// An ENR, or one of its Waku fields, is malformed or could not be built
+    InvalidEnr(String),
+    /// An ENR tree could not be built or failed verification
+    InvalidEnrTree(String),
+    /// A value cannot be handed over to libwaku, e.g. a string holding a NUL byte
+    InvalidArgument(String),
+    /// The node was stopped through another clone of its handle
+    NotRunning,
+    /// libwaku did not answer a request before its deadline
+    Timeout,
+    /// libwaku was called without the callback it requires
+    MissingCallback,
+    /// libwaku answered with a return code unknown to the bindings
+    UnknownReturnCode(u32),
+    /// A handler invoked from a libwaku callback panicked, carrying the panic message
+    CallbackPanic(String),
+}
+
+impl Display for WakuError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WakuError::Libwaku(msg) => write!(f, "waku error: {msg}"),
+            WakuError::Decode(msg) => write!(f, "decode error: {msg}"),
+            WakuError::InvalidConfig(msg) => write!(f, "invalid config: {msg}"),
+            WakuError::InvalidTopic(msg) => write!(f, "invalid topic: {msg}"),
+            WakuError::InvalidPeerId(msg) => write!(f, "invalid peer id: {msg}"),
+            WakuError::InvalidEnr(msg) => write!(f, "invalid enr: {msg}"),
+            WakuError::InvalidEnrTree(msg) => write!(f, "invalid enr tree: {msg}"),
+            WakuError::InvalidArgument(msg) => write!(f, "invalid argument: {msg}"),
+            WakuError::NotRunning => f.write_str("the node is not running"),
+            WakuError::Timeout => f.write_str("libwaku did not answer before the deadline"),
+            WakuError::MissingCallback => f.write_str("callback is required"),
+            WakuError::UnknownReturnCode(code) => write!(f, "undefined return code {code}"),
+            WakuError::CallbackPanic(msg) => write!(f, "callback panicked: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for WakuError {}
+
+impl From<serde_json::Error> for WakuError {
+    fn from(e: serde_json::Error) -> Self {
+        WakuError::Decode(e.to_string())
+    }
+}
 
 // TODO: Properly type and deserialize payload form base64 encoded string
 /// Waku message in JSON format.
@@ -131,7 +191,7 @@ impl WakuContentTopic {
 }
 
 impl FromStr for WakuContentTopic {
-    type Err = String;
+    type Err = WakuError;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         if let Ok((application_name, version, content_topic_name, encoding)) =
@@ -144,11 +204,11 @@ impl FromStr for WakuContentTopic {
                 encoding,
             })
         } else {
-            Err(
+            Err(WakuError::InvalidTopic(
                 format!(
                     "Wrong pub-sub topic format. Should be `/{{application-name}}/{{version-of-the-application}}/{{content-topic-name}}/{{encoding}}`. Got: {s}"
                 )
-            )
+            ))
         }
     }
 }
@@ -1,13 +1,14 @@
 //! # Waku
 //!
 //! Implementation on top of [`waku-bindings`](https://rfc.vac.dev/spec/36/)
+pub mod discovery;
 mod general;
 pub mod node;
 pub mod utils;
 use std::time::SystemTime;
 
 // Re-export the LibwakuResponse type to make it accessible outside this module
-pub use utils::LibwakuResponse;
+pub use utils::{set_callback_error_hook, LibwakuResponse};
 
 // Required so functions inside libwaku can call RLN functions even if we
 // use it within the bindings functions
@@ -16,17 +17,22 @@ pub use utils::LibwakuResponse;
 use rln;
 
 pub use node::{
-    waku_create_content_topic, waku_destroy, waku_new, Event, Initialized, Key, Multiaddr,
-    PublicKey, RLNConfig, Running, SecretKey, WakuMessageEvent, WakuNodeConfig, WakuNodeContext,
-    WakuNodeHandle,
+    waku_create_content_topic, waku_destroy, waku_new, ConnectionDirection, DnsInfo, Enr, Event,
+    EventStreamConfig, Initialized, Key, LatencyTrackerConfig, LogLevel, MessageProtocol,
+    Multiaddr, NodeInfo, OverflowPolicy, PagingDirection, PeerAddress, PeerId, PeerInfo,
+    PeerLatency, PeerLatencyTracker, PublicKey, RLNConfig, RelayShards, RetentionPolicy, Running,
+    SecretKey, StoreQuery, StoredMessage, WakuCapabilities, WakuEnr, WakuEnrBuilder,
+    WakuEventStream, WakuMessageEvent, WakuNodeConfig, WakuNodeConfigBuilder, WakuNodeContext,
+    WakuNodeHandle, DEFAULT_NAMESERVER, FILTER_SUBSCRIBE_PROTOCOL, LIGHTPUSH_PROTOCOL,
+    PEER_EXCHANGE_PROTOCOL, RELAY_PROTOCOL, STORE_QUERY_PROTOCOL,
 };
 
 pub use general::{
-    Encoding, MessageHash, Result, WakuContentTopic, WakuMessage, WakuMessageVersion,
+    Encoding, MessageHash, Result, WakuContentTopic, WakuError, WakuMessage, WakuMessageVersion,
 };
 
 #[no_mangle]
-pub fn say_hello(waku: &WakuNodeHandle) {
+pub fn say_hello(waku: &WakuNodeHandle<Running>) {
     println!("hello world");
 }
 
@@ -36,13 +42,13 @@ struct Response {
 }
 
 #[no_mangle]
-pub fn waku_new_wrapper(
+pub async fn waku_new_wrapper(
     cluster_id: usize,
     shards: Vec<usize>,
-    discovery_url: &'static str,
+    discovery_url: &str,
     node_key: Option<SecretKey>,
     topic: &str,
-) -> Result<WakuNodeHandle> {
+) -> Result<WakuNodeHandle<Running>> {
     //let topic = "/waku/2/rs/16/32";
     let waku = waku_new(Some(WakuNodeConfig {
         port: Some(60010),
@@ -54,14 +60,14 @@ pub fn waku_new_wrapper(
         node_key: node_key,
         max_message_size: Some("1024KiB".to_string()),
         relay_topics: vec![topic.to_string()],
-        log_level: Some("ERROR"), // Supported: TRACE, DEBUG, INFO, NOTICE, WARN, ERROR or FATAL
+        log_level: Some(LogLevel::Error),
 
         keep_alive: Some(true),
 
         // Discovery
         dns_discovery: Some(true),
         //dns_discovery_url: Some("enrtree://AMOJVZX4V6EXP7NTJPMAYJYST2QP6AJXYW76IU6VGJS7UVSNDYZG4@boot.prod.status.nodes.status.im"),
-        dns_discovery_url: Some(discovery_url),
+        dns_discovery_url: Some(discovery_url.to_string()),
         discv5_discovery: Some(true),
         discv5_udp_port: Some(9000),
         discv5_enr_auto_update: Some(false),
@@ -70,18 +76,21 @@ pub fn waku_new_wrapper(
     }))
     .expect("should instantiate");
 
-    if let Ok(_) = waku.start() {
-        println!("It works!");
-    } else {
-        println!("It didn't work. Let's go home xD");
+    match waku.start().await {
+        Ok(waku) => {
+            println!("It works!");
+            Ok(waku)
+        }
+        Err(e) => {
+            println!("It didn't work. Let's go home xD");
+            Err(e)
+        }
     }
-
-    Ok(waku)
 }
 
 #[no_mangle]
-pub fn waku_listen(
-    waku: &WakuNodeHandle,
+pub async fn waku_listen(
+    waku: &WakuNodeHandle<Running>,
     pubsub_topic: &str,
     content_topic: &str,
     tx: tokio::sync::mpsc::Sender<Response>,
@@ -112,8 +121,9 @@ pub fn waku_listen(
     };
 
     // Establish a closure that handles the incoming messages
-    waku.ctx.waku_set_event_callback(my_closure);
-    waku.relay_subscribe(&pubsub_topic.to_string())
+    waku.set_event_callback(my_closure);
+    waku.relay_subscribe(pubsub_topic)
+        .await
         .expect("waku should subscribe");
 
     // Wait for Ctrl+C (SIGINT) signal
@@ -131,7 +141,7 @@ pub fn waku_listen(
 }
 
 #[no_mangle]
-pub fn waku_send(waku: &WakuNodeHandle, pubsub_topic: &str, content_topic: &str, payload: String) {
+pub async fn waku_send(waku: &WakuNodeHandle<Running>, pubsub_topic: &str, content_topic: &str, payload: String) {
     let content_topic: WakuContentTopic = content_topic.parse().unwrap();
 
     let message = WakuMessage::new(
@@ -147,7 +157,7 @@ pub fn waku_send(waku: &WakuNodeHandle, pubsub_topic: &str, content_topic: &str,
         Vec::new(),
         false,
     );
-    match waku.relay_publish_message(&message, &pubsub_topic.to_string(), None) {
+    match waku.relay_publish_message(&message, pubsub_topic, None).await {
         Ok(r) => println!("ok: {:?}", r),
         Err(e) => println!("err: {:?}", e),
     };
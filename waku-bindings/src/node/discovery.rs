@@ -0,0 +1,261 @@
+//! Waku [discovery](https://rfc.vac.dev/spec/38/) related methods
+
+// std
+use std::ffi::CString;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+// crates
+use libc::*;
+use multiaddr::Multiaddr;
+use waku_sys::WakuCallBack;
+// internal
+use crate::general::{Result, WakuError};
+use crate::node::events::WakuNodeContext;
+use crate::node::peers::{waku_dial_peer, PeerId};
+use crate::node::waku_enr::WakuEnr;
+use crate::node::Enr;
+use crate::utils::{
+    handle_json_response, handle_no_response, request_deadline, timeout_millis, to_c_string,
+    waku_request_async, WakuDecode, DEFAULT_REQUEST_DEADLINE,
+};
+
+/// Protocol id of the [peer exchange](https://rfc.vac.dev/spec/34/) protocol
+pub const PEER_EXCHANGE_PROTOCOL: &str = "/vac/waku/peer-exchange/2.0.0-alpha1";
+
+/// Nameserver used for DNS discovery when none is given
+pub const DEFAULT_NAMESERVER: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+
+/// Peer found through [DNS discovery](https://eips.ethereum.org/EIPS/eip-1459)
+#[derive(Clone, Debug)]
+pub struct DnsInfo {
+    /// Libp2p peer id
+    pub peer_id: PeerId,
+    /// Addresses the peer can be dialed at
+    pub addresses: Vec<Multiaddr>,
+    /// Record of the peer
+    pub enr: Enr,
+}
+
+impl From<Enr> for DnsInfo {
+    fn from(enr: Enr) -> Self {
+        let enr = WakuEnr::from(enr);
+        Self {
+            peer_id: enr.peer_id(),
+            addresses: enr.addresses(),
+            enr: enr.into_enr(),
+        }
+    }
+}
+
+// libwaku answers the records it resolved as a JSON array
+impl WakuDecode for Vec<DnsInfo> {
+    fn decode(input: &str) -> Result<Self> {
+        let enrs: Vec<Enr> = serde_json::from_str(input)?;
+        Ok(enrs.into_iter().map(DnsInfo::from).collect())
+    }
+}
+
+fn dns_discovery_request<'a>(
+    ctx: &'a WakuNodeContext,
+    enrtree_url: &'a str,
+    nameserver: IpAddr,
+    timeout: Option<Duration>,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let enrtree_url = to_c_string(enrtree_url, WakuError::InvalidEnrTree)?;
+    let nameserver = to_c_string(nameserver.to_string(), WakuError::InvalidArgument)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let enrtree_url_ptr = enrtree_url.into_raw();
+        let nameserver_ptr = nameserver.into_raw();
+
+        let out = waku_sys::waku_dns_discovery(
+            ctx.obj_ptr,
+            enrtree_url_ptr,
+            nameserver_ptr,
+            timeout_millis(timeout),
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(enrtree_url_ptr));
+        drop(CString::from_raw(nameserver_ptr));
+
+        out
+    })
+}
+
+/// Resolve the peers published in the `enrtree://` tree at `enrtree_url`, querying `nameserver`,
+/// [`DEFAULT_NAMESERVER`] if `None`.
+/// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
+/// As per the [specification](https://eips.ethereum.org/EIPS/eip-1459)
+pub async fn waku_dns_discovery(
+    ctx: &WakuNodeContext,
+    enrtree_url: &str,
+    nameserver: Option<IpAddr>,
+    timeout: Option<Duration>,
+) -> Result<Vec<DnsInfo>> {
+    let result = waku_request_async(
+        request_deadline(timeout),
+        dns_discovery_request(
+            ctx,
+            enrtree_url,
+            nameserver.unwrap_or(DEFAULT_NAMESERVER),
+            timeout,
+        )?,
+    )
+    .await;
+
+    handle_json_response(result?)
+}
+
+// The received peers are answered as a JSON array of records
+impl WakuDecode for Vec<Enr> {
+    fn decode(input: &str) -> Result<Self> {
+        Ok(serde_json::from_str(input)?)
+    }
+}
+
+fn peer_exchange_request<'a>(
+    ctx: &'a WakuNodeContext,
+    num_peers: usize,
+    peer_id: &'a PeerId,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let peer_id = to_c_string(peer_id.to_string(), WakuError::InvalidPeerId)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let peer_id_ptr = peer_id.into_raw();
+
+        let out = waku_sys::waku_peer_exchange_request(
+            ctx.obj_ptr,
+            num_peers.try_into().unwrap_or(c_int::MAX),
+            peer_id_ptr,
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(peer_id_ptr));
+
+        out
+    })
+}
+
+/// Ask the peer exchange service node `peer` for up to `num_peers` of its peers, returning their records.
+/// The received peers are added to the peerstore.
+/// `peer` must end with its `/p2p/` peer id.
+/// As per the [specification](https://rfc.vac.dev/spec/34/)
+pub async fn waku_peer_exchange_request(
+    ctx: &WakuNodeContext,
+    num_peers: usize,
+    peer: &Multiaddr,
+) -> Result<Vec<Enr>> {
+    let peer_id = PeerId::from_dialable(peer)?;
+    waku_dial_peer(ctx, peer, PEER_EXCHANGE_PROTOCOL, None).await?;
+
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        peer_exchange_request(ctx, num_peers, &peer_id)?,
+    )
+    .await;
+
+    handle_json_response(result?)
+}
+
+/// Start discv5, which requires the node to be created with [`crate::WakuNodeConfig::discv5_discovery`] enabled
+pub async fn waku_start_discv5(ctx: &WakuNodeContext) -> Result<()> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_start_discv5(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
+
+    handle_no_response(result?)
+}
+
+/// Stop discv5, the peers already found are kept
+pub async fn waku_stop_discv5(ctx: &WakuNodeContext) -> Result<()> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_stop_discv5(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
+
+    handle_no_response(result?)
+}
+
+fn update_bootnodes_request<'a>(
+    ctx: &'a WakuNodeContext,
+    bootnodes: &'a [Enr],
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let bootnodes = to_c_string(
+        serde_json::to_string(bootnodes).expect("ENRs should always serialize properly"),
+        WakuError::InvalidArgument,
+    )?;
+
+    Ok(move |cb, user_data| unsafe {
+        let bootnodes_ptr = bootnodes.into_raw();
+
+        let out = waku_sys::waku_discv5_update_bootnodes(ctx.obj_ptr, bootnodes_ptr, cb, user_data);
+
+        drop(CString::from_raw(bootnodes_ptr));
+
+        out
+    })
+}
+
+/// Replace the discv5 bootstrap nodes of a running node
+pub async fn waku_discv5_update_bootnodes(ctx: &WakuNodeContext, bootnodes: &[Enr]) -> Result<()> {
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        update_bootnodes_request(ctx, bootnodes)?,
+    )
+    .await;
+
+    handle_no_response(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DnsInfo;
+    use crate::utils::WakuDecode;
+    use crate::{Enr, PeerId};
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+
+    fn enr() -> Enr {
+        let key = enr::secp256k1::SecretKey::from_str(
+            "05f381866cc21f6c1e2e80e07fa732008e36d942dce3206ad6dcd6793c98d609",
+        )
+        .unwrap();
+        enr::EnrBuilder::new("v4")
+            .ip4(Ipv4Addr::LOCALHOST)
+            .tcp4(60010)
+            .build(&key)
+            .unwrap()
+    }
+
+    #[test]
+    fn decode_dns_records() {
+        let enr = enr();
+        let s = format!("[\"{}\"]", enr.to_base64());
+        let peers = Vec::<DnsInfo>::decode(&s).unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].peer_id, PeerId::from(&enr));
+        assert_eq!(peers[0].enr, enr);
+        assert_eq!(
+            peers[0].addresses[0].to_string(),
+            "/ip4/127.0.0.1/tcp/60010/p2p/16Uiu2HAm34iMHeFfugScosRWkMArFBTiqaC1ai4Lga5zt6vG47qr"
+        );
+
+        assert!(Vec::<DnsInfo>::decode("[\"/ip4/127.0.0.1/tcp/60010\"]").is_err());
+        assert!(Vec::<DnsInfo>::decode("[\"enr:-invalid\"]").is_err());
+    }
+
+    #[test]
+    fn decode_peer_exchange_records() {
+        let enr = enr();
+        let s = format!("[\"{}\"]", enr.to_base64());
+        assert_eq!(Vec::<Enr>::decode(&s).unwrap(), vec![enr]);
+
+        assert!(Vec::<Enr>::decode("[]").unwrap().is_empty());
+        assert!(Vec::<Enr>::decode("3").is_err());
+        assert!(Vec::<Enr>::decode("[\"enr:-invalid\"]").is_err());
+    }
+}
@@ -5,24 +5,59 @@
 //! When an event is emitted, this callback will be triggered receiving an [`Event`]
 
 // std
+use std::collections::BTreeSet;
 use std::ffi::c_void;
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::task::{Context, Poll};
 // crates
+use futures::Stream;
 use serde::{Deserialize, Serialize};
+use smart_default::SmartDefault;
+use tokio::sync::mpsc;
 // internal
-use crate::general::WakuMessage;
-use std::{slice, str};
-
-use crate::utils::LibwakuResponse;
+use crate::general::{Result, WakuError, WakuMessage};
+use crate::node::config::WakuNodeConfig;
+use crate::node::management::{waku_destroy, waku_destroy_blocking, waku_stop, waku_stop_blocking};
+use crate::utils::{guard_callback, read_response, report_callback_error, LibwakuResponse};
 use crate::MessageHash;
-use std::ops::Deref;
-use std::sync::Mutex;
-// crates
-use once_cell::sync::Lazy;
 
+/// User closure handling the events of a node
+type EventHandler = Arc<Mutex<Box<dyn FnMut(LibwakuResponse) + Send + Sync>>>;
+
+/// Event handler owned by a single node, invoked by libwaku through the `user_data` pointer.
+/// The outer lock is only held to swap or clone the handler, never while it runs,
+/// so a handler can replace itself or register an event stream without deadlocking.
+type EventObserver = Mutex<EventHandler>;
+
+/// Native node context plus the event handler registered for it.
+/// Dropping the context shuts the native node down, unless that was already done explicitly,
+/// blocking the current thread until libwaku answers, for up to a minute for stopping
+/// the node and as much for destroying it.
 pub struct WakuNodeContext {
-    pub obj_ptr: *mut c_void,
+    pub(crate) obj_ptr: *mut c_void,
+    /// Boxed so its address stays stable while libwaku holds it as `user_data`,
+    /// even when the context is moved between handles.
+    /// Only released once libwaku confirmed destroying the node, it could still call it otherwise
+    msg_observer: ManuallyDrop<Box<EventObserver>>,
+    /// Pubsub topics the node is relay subscribed to
+    relay_subscriptions: RwLock<BTreeSet<String>>,
+    /// Configuration the node was created with
+    config: WakuNodeConfig,
+    running: AtomicBool,
+    destroyed: bool,
+    destroy_confirmed: bool,
 }
 
+// SAFETY: libwaku entry points only hand the request over to the node's own thread,
+// under a lock of the native context, so `obj_ptr` can be used from any thread at once.
+// The event handler is only reached through its mutex.
+// Destroying the node requires owning the context, so no request can be in flight then.
+unsafe impl Send for WakuNodeContext {}
+unsafe impl Sync for WakuNodeContext {}
+
 /// Waku event
 /// For now just WakuMessage is supported
 #[non_exhaustive]
@@ -44,66 +79,290 @@ pub struct WakuMessageEvent {
     pub message_hash: MessageHash,
     /// The message in [`WakuMessage`] format
     pub waku_message: WakuMessage,
+    /// The protocol that delivered the message, `None` if libwaku did not report it
+    #[serde(default)]
+    pub protocol: Option<MessageProtocol>,
+}
+
+/// Protocol through which a [`WakuMessageEvent`] reached the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageProtocol {
+    /// Received from the relay mesh
+    Relay,
+    /// Pushed by a filter service node
+    Filter,
+    /// Returned by a store query
+    Store,
+}
+
+/// What to do with an incoming event when the [`WakuEventStream`] buffer is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the incoming event, keeping the ones already buffered
+    #[default]
+    DropNewest,
+    /// Block the libwaku thread delivering the event until the stream is polled.
+    /// Stalls the node while the consumer lags behind.
+    Block,
 }
 
-#[allow(clippy::type_complexity)]
-static CALLBACK: Lazy<Mutex<Box<dyn FnMut(LibwakuResponse) + Send + Sync>>> =
-    Lazy::new(|| Mutex::new(Box::new(|_| {})));
+/// Buffering settings for a [`WakuEventStream`]
+#[derive(Clone, Copy, Debug, SmartDefault)]
+pub struct EventStreamConfig {
+    /// Maximum number of events buffered before `overflow` applies. Default `1024`
+    #[default(1024)]
+    pub capacity: usize,
+    /// Behaviour once `capacity` events are buffered
+    pub overflow: OverflowPolicy,
+}
+
+/// Stream of decoded node [`Event`]s.
+/// An event that cannot be decoded is yielded as an `Err` instead of being dropped.
+/// The stream ends once another event handler is registered on the node.
+pub struct WakuEventStream {
+    receiver: mpsc::Receiver<Result<Event>>,
+}
 
-/// Register global callback
-fn set_callback<F: FnMut(LibwakuResponse) + Send + Sync + 'static>(f: F) {
-    *CALLBACK.lock().unwrap() = Box::new(f);
+impl Stream for WakuEventStream {
+    type Item = Result<Event>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Turn a raw event callback response into an [`Event`].
+/// Event types unknown to this crate become [`Event::Unrecognized`],
+/// `None` is returned for responses that carry no event at all.
+fn decode_event(response: LibwakuResponse) -> Option<Result<Event>> {
+    match response {
+        LibwakuResponse::Success(Some(json)) => {
+            let value: serde_json::Value = match serde_json::from_str(&json) {
+                Ok(value) => value,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let known_type = matches!(value.get("eventType"), Some(t) if t == "message");
+            Some(match serde_json::from_value::<Event>(value.clone()) {
+                Ok(event) => Ok(event),
+                Err(e) if known_type => Err(e.into()),
+                Err(_) => Ok(Event::Unrecognized(value)),
+            })
+        }
+        LibwakuResponse::Success(None) => None,
+        LibwakuResponse::Failure(v) => Some(Err(WakuError::Libwaku(v))),
+        LibwakuResponse::MissingCallback | LibwakuResponse::Undefined => None,
+    }
 }
 
+/// Event entry point called by libwaku. Malformed responses and handler
+/// panics are reported through the callback error hook, as unwinding
+/// into libwaku is undefined behavior.
 unsafe extern "C" fn callback(
     ret_code: ::std::os::raw::c_int,
     data: *const ::std::os::raw::c_char,
     data_len: usize,
     user_data: *mut ::std::os::raw::c_void,
 ) {
-    let response = if data.is_null() {
-        ""
-    } else {
-        str::from_utf8(slice::from_raw_parts(data as *mut u8, data_len))
-            .expect("could not retrieve response")
-    };
-
-    let result = LibwakuResponse::try_from((ret_code as u32, response))
-        .expect("invalid response obtained from libwaku");
-
-    (CALLBACK
-        .deref()
-        .lock()
-        .expect("Access to the shared callback")
-        .as_mut())(result);
+    guard_callback(|| {
+        let observer = &*(user_data as *const EventObserver);
+        match read_response(ret_code, data, data_len) {
+            Ok(response) => {
+                let handler = Arc::clone(&observer.lock().unwrap_or_else(PoisonError::into_inner));
+                let mut handler = handler.lock().unwrap_or_else(PoisonError::into_inner);
+                (handler.as_mut())(response)
+            }
+            Err(err) => report_callback_error(err),
+        }
+    });
+}
+
+/// Swap the handler of `observer` for `closure`. A call still running the previous
+/// handler completes with it.
+fn replace_handler<F: FnMut(LibwakuResponse) + 'static + Sync + Send>(
+    observer: &EventObserver,
+    closure: F,
+) {
+    *observer.lock().unwrap_or_else(PoisonError::into_inner) =
+        Arc::new(Mutex::new(Box::new(closure)));
 }
 
 impl WakuNodeContext {
-    /// Register callback to act as event handler and receive application events,
-    /// which are used to react to asynchronous events in Waku
-    pub fn waku_set_event_callback<F: FnMut(LibwakuResponse) + 'static + Sync + Send>(
-        &self,
-        mut closure: F,
-    ) {
-        set_callback(closure);
+    /// Wrap a freshly created native node, registering a no-op event handler
+    /// so libwaku always has a valid callback to invoke
+    pub(crate) fn new(obj_ptr: *mut c_void, config: WakuNodeConfig) -> Self {
+        let msg_observer: Box<EventObserver> =
+            Box::new(Mutex::new(Arc::new(Mutex::new(Box::new(|_| {})))));
         unsafe {
             waku_sys::waku_set_event_callback(
-                self.obj_ptr,
+                obj_ptr,
                 Some(callback),
-                callback as *mut c_void,
+                &*msg_observer as *const EventObserver as *mut c_void,
             );
         }
+        Self {
+            obj_ptr,
+            msg_observer: ManuallyDrop::new(msg_observer),
+            relay_subscriptions: RwLock::new(config.relay_subscriptions()),
+            config,
+            running: AtomicBool::new(false),
+            destroyed: false,
+            destroy_confirmed: false,
+        }
+    }
+
+    /// Pubsub topics the node is relay subscribed to
+    pub(crate) fn relay_subscriptions(&self) -> &RwLock<BTreeSet<String>> {
+        &self.relay_subscriptions
+    }
+
+    /// Configuration the node was created with
+    pub(crate) fn config(&self) -> &WakuNodeConfig {
+        &self.config
+    }
+
+    /// Whether the native node is started
+    pub(crate) fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Record whether the native node is started, so it can be stopped before being destroyed
+    pub(crate) fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::SeqCst);
+    }
+
+    /// Stop the native node if it is running, then destroy it.
+    /// Destruction is attempted only once, even if it fails.
+    pub(crate) async fn shutdown(&mut self) -> Result<()> {
+        let stopped = if *self.running.get_mut() {
+            waku_stop(self).await
+        } else {
+            Ok(())
+        };
+        self.destroyed = true;
+        let destroyed = waku_destroy(self).await;
+        self.destroy_confirmed = destroyed.is_ok();
+        stopped.and(destroyed)
+    }
+
+    /// Blocking version of [`WakuNodeContext::shutdown`], for when the context is dropped
+    fn shutdown_blocking(&mut self) -> Result<()> {
+        let stopped = if *self.running.get_mut() {
+            waku_stop_blocking(self)
+        } else {
+            Ok(())
+        };
+        self.destroyed = true;
+        let destroyed = waku_destroy_blocking(self);
+        self.destroy_confirmed = destroyed.is_ok();
+        stopped.and(destroyed)
+    }
+
+    /// Register callback to act as event handler and receive application events,
+    /// which are used to react to asynchronous events in Waku.
+    /// Only this node's handler is replaced, other nodes keep their own.
+    pub fn waku_set_event_callback<F: FnMut(LibwakuResponse) + 'static + Sync + Send>(
+        &self,
+        closure: F,
+    ) {
+        replace_handler(&self.msg_observer, closure);
+    }
+
+    /// Replace the node event handler with one feeding a new [`WakuEventStream`]
+    pub fn waku_event_stream(&self, config: EventStreamConfig) -> WakuEventStream {
+        let (sender, receiver) = mpsc::channel(config.capacity.max(1));
+        self.waku_set_event_callback(move |response| {
+            let Some(event) = decode_event(response) else {
+                return;
+            };
+            match config.overflow {
+                // A full buffer drops the event, a closed one means nobody listens anymore
+                OverflowPolicy::DropNewest => {
+                    let _ = sender.try_send(event);
+                }
+                OverflowPolicy::Block => {
+                    let _ = sender.blocking_send(event);
+                }
+            }
+        });
+        WakuEventStream { receiver }
+    }
+}
+
+impl Drop for WakuNodeContext {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            if let Err(e) = self.shutdown_blocking() {
+                report_callback_error(e);
+            }
+        }
+        if self.destroy_confirmed {
+            // SAFETY: the node is gone, libwaku holds no pointer to the handler anymore
+            unsafe { ManuallyDrop::drop(&mut self.msg_observer) };
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Event;
+    use super::{callback, decode_event, replace_handler, EventObserver, MessageProtocol};
+    use crate::{Event, LibwakuResponse, WakuError};
+    use std::ffi::{c_int, c_void};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn deserialize_message_event() {
         let s = "{\"eventType\":\"message\",\"messageHash\":\"0x26ff3d7fbc950ea2158ce62fd76fd745eee0323c9eac23d0713843b0f04ea27c\",\"pubsubTopic\":\"/waku/2/default-waku/proto\",\"wakuMessage\":{\"payload\":\"SGkgZnJvbSDwn6aAIQ==\",\"contentTopic\":\"/toychat/2/huilong/proto\",\"timestamp\":1665580926660}}";
         let evt: Event = serde_json::from_str(s).unwrap();
-        assert!(matches!(evt, Event::WakuMessage(_)));
+        assert!(matches!(evt, Event::WakuMessage(evt) if evt.protocol.is_none()));
+    }
+
+    #[test]
+    fn deserialize_message_protocol() {
+        let s = "{\"eventType\":\"message\",\"messageHash\":\"0x26ff3d7fbc950ea2158ce62fd76fd745eee0323c9eac23d0713843b0f04ea27c\",\"pubsubTopic\":\"/waku/2/default-waku/proto\",\"protocol\":\"filter\",\"wakuMessage\":{\"payload\":\"SGkgZnJvbSDwn6aAIQ==\",\"contentTopic\":\"/toychat/2/huilong/proto\",\"timestamp\":1665580926660}}";
+        let evt: Event = serde_json::from_str(s).unwrap();
+        assert!(
+            matches!(evt, Event::WakuMessage(evt) if evt.protocol == Some(MessageProtocol::Filter))
+        );
+    }
+
+    #[test]
+    fn decode_unknown_event_type() {
+        let s = "{\"eventType\":\"connectionChange\",\"peerId\":\"16Uiu2HAm\"}";
+        let evt = decode_event(LibwakuResponse::Success(Some(s.to_string())));
+        assert!(matches!(evt, Some(Ok(Event::Unrecognized(_)))));
+    }
+
+    #[test]
+    fn decode_malformed_message_event() {
+        let s = "{\"eventType\":\"message\",\"pubsubTopic\":\"/waku/2/default-waku/proto\"}";
+        let evt = decode_event(LibwakuResponse::Success(Some(s.to_string())));
+        assert!(matches!(evt, Some(Err(WakuError::Decode(_)))));
+
+        let evt = decode_event(LibwakuResponse::Success(Some("not json".to_string())));
+        assert!(matches!(evt, Some(Err(_))));
+    }
+
+    #[test]
+    fn handler_can_replace_itself() {
+        let observer: Arc<EventObserver> =
+            Arc::new(Mutex::new(Arc::new(Mutex::new(Box::new(|_| {})))));
+        let replaced = Arc::new(AtomicUsize::new(0));
+        let calls = Arc::clone(&replaced);
+        let inner = Arc::clone(&observer);
+        replace_handler(&observer, move |_| {
+            let calls = Arc::clone(&calls);
+            replace_handler(&inner, move |_| {
+                calls.fetch_add(1, Ordering::SeqCst);
+            });
+        });
+
+        let user_data = &*observer as *const EventObserver as *mut c_void;
+        unsafe {
+            callback(waku_sys::RET_OK as c_int, std::ptr::null(), 0, user_data);
+            callback(waku_sys::RET_OK as c_int, std::ptr::null(), 0, user_data);
+        }
+        assert_eq!(replaced.load(Ordering::SeqCst), 1);
     }
 }
@@ -0,0 +1,162 @@
+//! Waku [filter](https://rfc.vac.dev/spec/12/) client protocol related methods
+
+// std
+use std::ffi::CString;
+// crates
+use libc::*;
+use multiaddr::Multiaddr;
+use waku_sys::WakuCallBack;
+// internal
+use crate::general::{Result, WakuContentTopic, WakuError};
+use crate::node::events::WakuNodeContext;
+use crate::node::peers::{waku_dial_peer, PeerId};
+use crate::utils::{handle_no_response, to_c_string, waku_request_async, DEFAULT_REQUEST_DEADLINE};
+
+/// Protocol id of the filter v2 subscribe protocol
+pub const FILTER_SUBSCRIBE_PROTOCOL: &str = "/vac/waku/filter-subscribe/2.0.0-beta1";
+
+/// Content topics as expected by libwaku, comma separated
+fn join_content_topics(content_topics: &[WakuContentTopic]) -> String {
+    content_topics
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn filter_subscribe_request<'a>(
+    ctx: &'a WakuNodeContext,
+    pubsub_topic: &'a str,
+    content_topics: &'a [WakuContentTopic],
+    peer_id: &'a PeerId,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let pubsub_topic = to_c_string(pubsub_topic, WakuError::InvalidTopic)?;
+    let content_topics = to_c_string(join_content_topics(content_topics), WakuError::InvalidTopic)?;
+    let peer_id = to_c_string(peer_id.to_string(), WakuError::InvalidPeerId)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let pubsub_topic_ptr = pubsub_topic.into_raw();
+        let content_topics_ptr = content_topics.into_raw();
+        let peer_id_ptr = peer_id.into_raw();
+
+        let out = waku_sys::waku_filter_subscribe(
+            ctx.obj_ptr,
+            pubsub_topic_ptr,
+            content_topics_ptr,
+            peer_id_ptr,
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(pubsub_topic_ptr));
+        drop(CString::from_raw(content_topics_ptr));
+        drop(CString::from_raw(peer_id_ptr));
+
+        out
+    })
+}
+
+fn filter_unsubscribe_request<'a>(
+    ctx: &'a WakuNodeContext,
+    pubsub_topic: &'a str,
+    content_topics: &'a [WakuContentTopic],
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let pubsub_topic = to_c_string(pubsub_topic, WakuError::InvalidTopic)?;
+    let content_topics = to_c_string(join_content_topics(content_topics), WakuError::InvalidTopic)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let pubsub_topic_ptr = pubsub_topic.into_raw();
+        let content_topics_ptr = content_topics.into_raw();
+
+        let out = waku_sys::waku_filter_unsubscribe(
+            ctx.obj_ptr,
+            pubsub_topic_ptr,
+            content_topics_ptr,
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(pubsub_topic_ptr));
+        drop(CString::from_raw(content_topics_ptr));
+
+        out
+    })
+}
+
+/// Subscribe to messages matching `content_topics` on `pubsub_topic`, pushed by the filter service node `peer`.
+/// `peer` must end with its `/p2p/` peer id.
+/// As per the [specification](https://rfc.vac.dev/spec/12/#subscribe)
+pub async fn waku_filter_subscribe(
+    ctx: &WakuNodeContext,
+    peer: &Multiaddr,
+    pubsub_topic: &str,
+    content_topics: &[WakuContentTopic],
+) -> Result<()> {
+    let peer_id = PeerId::from_dialable(peer)?;
+    waku_dial_peer(ctx, peer, FILTER_SUBSCRIBE_PROTOCOL, None).await?;
+
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        filter_subscribe_request(ctx, pubsub_topic, content_topics, &peer_id)?,
+    )
+    .await;
+
+    handle_no_response(result?)
+}
+
+fn filter_ping_request<'a>(
+    ctx: &'a WakuNodeContext,
+    peer_id: &'a PeerId,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let peer_id = to_c_string(peer_id.to_string(), WakuError::InvalidPeerId)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let peer_id_ptr = peer_id.into_raw();
+
+        let out = waku_sys::waku_filter_ping(ctx.obj_ptr, peer_id_ptr, cb, user_data);
+
+        drop(CString::from_raw(peer_id_ptr));
+
+        out
+    })
+}
+
+/// Check that the filter service node `peer` still holds the subscriptions of this node
+/// As per the [specification](https://rfc.vac.dev/spec/12/#subscriber-ping)
+pub async fn waku_filter_ping(ctx: &WakuNodeContext, peer: &Multiaddr) -> Result<()> {
+    let peer_id = PeerId::from_dialable(peer)?;
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        filter_ping_request(ctx, &peer_id)?,
+    )
+    .await;
+
+    handle_no_response(result?)
+}
+
+/// Stop receiving messages matching `content_topics` on `pubsub_topic`
+/// As per the [specification](https://rfc.vac.dev/spec/12/#unsubscribe)
+pub async fn waku_filter_unsubscribe(
+    ctx: &WakuNodeContext,
+    pubsub_topic: &str,
+    content_topics: &[WakuContentTopic],
+) -> Result<()> {
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        filter_unsubscribe_request(ctx, pubsub_topic, content_topics)?,
+    )
+    .await;
+
+    handle_no_response(result?)
+}
+
+/// Stop receiving any filter pushed message
+/// As per the [specification](https://rfc.vac.dev/spec/12/#unsubscribe-all)
+pub async fn waku_filter_unsubscribe_all(ctx: &WakuNodeContext) -> Result<()> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_filter_unsubscribe_all(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
+
+    handle_no_response(result?)
+}
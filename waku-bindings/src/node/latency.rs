@@ -0,0 +1,188 @@
+//! Round trip time tracking of peers, built on the libp2p [ping](https://github.com/libp2p/specs/blob/master/ping/ping.md) protocol
+
+// std
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+// crates
+use futures::future::join_all;
+use multiaddr::Multiaddr;
+use smart_default::SmartDefault;
+// internal
+use crate::node::{Running, WakuNodeHandle};
+
+/// Settings of a [`PeerLatencyTracker`]
+#[derive(Clone, Copy, Debug, SmartDefault)]
+pub struct LatencyTrackerConfig {
+    /// Time between two rounds of pings. Default 30 seconds
+    #[default(Duration::from_secs(30))]
+    pub interval: Duration,
+    /// Time after which a ping counts as failed. Default 10 seconds
+    #[default(Some(Duration::from_secs(10)))]
+    pub timeout: Option<Duration>,
+    /// Weight of the newest round trip time in the moving average, between `0` and `1`. Default `0.2`
+    #[default(0.2)]
+    pub smoothing: f64,
+}
+
+/// Latency measured for a tracked peer
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PeerLatency {
+    /// Exponential moving average of the round trip times, `None` until a ping succeeds
+    pub average: Option<Duration>,
+    /// Round trip time of the latest successful ping
+    pub last: Option<Duration>,
+    /// Pings failed since the latest successful one
+    pub consecutive_failures: u32,
+}
+
+impl PeerLatency {
+    fn record(&mut self, rtt: Duration, smoothing: f64) {
+        let smoothing = smoothing.clamp(0.0, 1.0);
+        self.average = Some(match self.average {
+            Some(average) => average.mul_f64(1.0 - smoothing) + rtt.mul_f64(smoothing),
+            None => rtt,
+        });
+        self.last = Some(rtt);
+        self.consecutive_failures = 0;
+    }
+
+    fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+    }
+
+    /// Whether the latest ping succeeded
+    pub fn is_reachable(&self) -> bool {
+        self.average.is_some() && self.consecutive_failures == 0
+    }
+}
+
+/// Keeps a moving average of the round trip time to a set of peers, pinging them periodically.
+/// Clones share the tracked peers and their latencies.
+/// The tracker keeps its node alive until the tracker and its clones are dropped.
+#[derive(Clone)]
+pub struct PeerLatencyTracker {
+    node: WakuNodeHandle<Running>,
+    config: LatencyTrackerConfig,
+    peers: Arc<RwLock<HashMap<Multiaddr, PeerLatency>>>,
+}
+
+impl PeerLatencyTracker {
+    /// Tracker pinging peers from `node`
+    pub fn new(node: WakuNodeHandle<Running>, config: LatencyTrackerConfig) -> Self {
+        Self {
+            node,
+            config,
+            peers: Arc::default(),
+        }
+    }
+
+    /// Start tracking `peer`, from its next round of pings
+    pub fn track(&self, peer: Multiaddr) {
+        self.peers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(peer)
+            .or_default();
+    }
+
+    /// Stop tracking `peer`, forgetting its latency
+    pub fn untrack(&self, peer: &Multiaddr) {
+        self.peers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(peer);
+    }
+
+    /// Latency measured for `peer`, `None` if it is not tracked
+    pub fn latency(&self, peer: &Multiaddr) -> Option<PeerLatency> {
+        self.peers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(peer)
+            .copied()
+    }
+
+    /// Reachable peers, lowest average round trip time first
+    pub fn ranked(&self) -> Vec<(Multiaddr, Duration)> {
+        let mut ranked: Vec<_> = self
+            .peers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(_, latency)| latency.is_reachable())
+            .filter_map(|(peer, latency)| Some((peer.clone(), latency.average?)))
+            .collect();
+        ranked.sort_by_key(|(_, average)| *average);
+        ranked
+    }
+
+    /// Ping every tracked peer once, concurrently, updating their latencies
+    pub async fn ping_all(&self) {
+        let peers: Vec<Multiaddr> = self
+            .peers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect();
+        let results = join_all(
+            peers
+                .iter()
+                .map(|peer| self.node.ping(peer, self.config.timeout)),
+        )
+        .await;
+
+        let mut tracked = self.peers.write().unwrap_or_else(PoisonError::into_inner);
+        for (peer, result) in peers.iter().zip(results) {
+            // Skip the peers untracked while being pinged
+            let Some(latency) = tracked.get_mut(peer) else {
+                continue;
+            };
+            match result {
+                Ok(rtt) => latency.record(rtt, self.config.smoothing),
+                Err(_) => latency.record_failure(),
+            }
+        }
+    }
+
+    /// Ping the tracked peers every [`LatencyTrackerConfig::interval`], forever.
+    /// Meant to be spawned on a runtime with timers enabled, e.g. `tokio::spawn(tracker.clone().run())`.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.config.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.ping_all().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PeerLatency;
+    use std::time::Duration;
+
+    #[test]
+    fn moving_average() {
+        let mut latency = PeerLatency::default();
+        assert!(!latency.is_reachable());
+
+        latency.record(Duration::from_millis(100), 0.5);
+        assert_eq!(latency.average, Some(Duration::from_millis(100)));
+
+        latency.record(Duration::from_millis(200), 0.5);
+        assert_eq!(latency.average, Some(Duration::from_millis(150)));
+        assert_eq!(latency.last, Some(Duration::from_millis(200)));
+
+        latency.record_failure();
+        latency.record_failure();
+        assert_eq!(latency.consecutive_failures, 2);
+        assert!(!latency.is_reachable());
+        assert_eq!(latency.average, Some(Duration::from_millis(150)));
+
+        latency.record(Duration::from_millis(50), 0.2);
+        assert_eq!(latency.average, Some(Duration::from_millis(130)));
+        assert!(latency.is_reachable());
+    }
+}
@@ -0,0 +1,84 @@
+//! Waku [lightpush](https://rfc.vac.dev/spec/19/) protocol related methods
+
+// std
+use std::ffi::CString;
+// crates
+use libc::*;
+use multiaddr::Multiaddr;
+use waku_sys::WakuCallBack;
+// internal
+use crate::general::{MessageHash, Result, WakuError, WakuMessage};
+use crate::node::events::WakuNodeContext;
+use crate::node::peers::{waku_dial_peer, PeerId};
+use crate::utils::{handle_response, to_c_string, waku_request_async, DEFAULT_REQUEST_DEADLINE};
+
+/// Protocol id of the lightpush protocol
+pub const LIGHTPUSH_PROTOCOL: &str = "/vac/waku/lightpush/2.0.0-beta1";
+
+fn lightpush_publish_request<'a>(
+    ctx: &'a WakuNodeContext,
+    message: &'a WakuMessage,
+    pubsub_topic: &'a str,
+    peer_id: Option<&'a PeerId>,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let message = to_c_string(
+        serde_json::to_string(&message)
+            .expect("WakuMessages should always be able to success serializing"),
+        WakuError::InvalidArgument,
+    )?;
+    let pubsub_topic = to_c_string(pubsub_topic, WakuError::InvalidTopic)?;
+    // An empty peer id lets libwaku pick the service node
+    let peer_id = to_c_string(
+        peer_id.map(ToString::to_string).unwrap_or_default(),
+        WakuError::InvalidPeerId,
+    )?;
+
+    Ok(move |cb, user_data| unsafe {
+        let message_ptr = message.into_raw();
+        let pubsub_topic_ptr = pubsub_topic.into_raw();
+        let peer_id_ptr = peer_id.into_raw();
+
+        let out = waku_sys::waku_lightpush_publish(
+            ctx.obj_ptr,
+            pubsub_topic_ptr,
+            message_ptr,
+            peer_id_ptr,
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(message_ptr));
+        drop(CString::from_raw(pubsub_topic_ptr));
+        drop(CString::from_raw(peer_id_ptr));
+
+        out
+    })
+}
+
+/// Publish a message through a lightpush service node.
+/// Without `peer`, libwaku picks one of the known lightpush service nodes.
+/// `peer` must end with its `/p2p/` peer id.
+/// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_lightpush_publishchar-messagejson-char-topic-char-peerid-int-timeoutms)
+pub async fn waku_lightpush_publish_message(
+    ctx: &WakuNodeContext,
+    message: &WakuMessage,
+    pubsub_topic: &str,
+    peer: Option<&Multiaddr>,
+) -> Result<MessageHash> {
+    let peer_id = match peer {
+        Some(peer) => {
+            let peer_id = PeerId::from_dialable(peer)?;
+            waku_dial_peer(ctx, peer, LIGHTPUSH_PROTOCOL, None).await?;
+            Some(peer_id)
+        }
+        None => None,
+    };
+
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        lightpush_publish_request(ctx, message, pubsub_topic, peer_id.as_ref())?,
+    )
+    .await;
+
+    handle_response(result?)
+}
@@ -2,103 +2,141 @@
 
 // std
 use std::ffi::CString;
+use std::sync::PoisonError;
 // crates
 use libc::c_void;
 use multiaddr::Multiaddr;
+use serde::Serialize;
+use waku_sys::WakuCallBack;
 // internal
 use super::config::WakuNodeConfig;
-use crate::general::Result;
+use crate::general::{Result, WakuError};
+use crate::node::events::WakuNodeContext;
+use crate::node::peers::PeerId;
+use crate::node::waku_enr::WakuEnr;
+use crate::node::Enr;
 use crate::utils::LibwakuResponse;
-use crate::utils::{get_trampoline, handle_json_response, handle_no_response, handle_response};
 use crate::utils::WakuDecode;
-use crate::node::events::WakuNodeContext;
+use crate::utils::{
+    handle_json_response, handle_no_response, handle_response, to_c_string, waku_immediate_request,
+    waku_request, waku_request_async, DEFAULT_REQUEST_DEADLINE,
+};
 
-/// Instantiates a Waku node
-/// as per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_newchar-jsonconfig)
-pub fn waku_new(config: Option<WakuNodeConfig>) -> Result<WakuNodeContext> {
-    unsafe {
-        waku_sys::waku_setup();
-    }
+/// Node pointer returned by `waku_new`, kept aside while the request completes
+struct NodePtr(*mut c_void);
 
-    let config = config.unwrap_or_default();
-    let config_ptr = CString::new(
+fn new_request(
+    config: &WakuNodeConfig,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> NodePtr> {
+    let config = to_c_string(
         serde_json::to_string(&config)
             .expect("Serialization from properly built NodeConfig should never fail"),
-    )
-    .expect("CString should build properly from the config")
-    .into_raw();
+        WakuError::InvalidConfig,
+    )?;
+
+    Ok(move |cb, user_data| unsafe {
+        waku_sys::waku_setup();
+
+        let config_ptr = config.into_raw();
 
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let obj_ptr = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        let out = waku_sys::waku_new(config_ptr, cb, &mut closure as *mut _ as *mut c_void);
+        let out = waku_sys::waku_new(config_ptr, cb, user_data);
 
         drop(CString::from_raw(config_ptr));
 
-        out
-    };
+        NodePtr(out)
+    })
+}
 
-    match result {
-        LibwakuResponse::MissingCallback => panic!("callback is required"),
-        LibwakuResponse::Failure(v) => Err(v),
-        _ => Ok(WakuNodeContext { obj_ptr }),
+/// `waku_new` only invokes the callback when it fails, before returning:
+/// a returned context without any response is a node created successfully
+fn handle_new_response(
+    NodePtr(obj_ptr): NodePtr,
+    config: WakuNodeConfig,
+    response: Option<Result<LibwakuResponse>>,
+) -> Result<WakuNodeContext> {
+    match response.transpose()? {
+        Some(LibwakuResponse::MissingCallback) => Err(WakuError::MissingCallback),
+        Some(LibwakuResponse::Failure(v)) => Err(WakuError::Libwaku(v)),
+        _ if obj_ptr.is_null() => Err(WakuError::Libwaku(
+            "no node context was returned".to_string(),
+        )),
+        _ => Ok(WakuNodeContext::new(obj_ptr, config)),
     }
 }
 
-pub fn waku_destroy(ctx: &WakuNodeContext) -> Result<()> {
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        waku_sys::waku_destroy(ctx.obj_ptr, cb, &mut closure as *mut _ as *mut c_void)
-    };
+/// Instantiates a Waku node
+/// as per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_newchar-jsonconfig)
+pub fn waku_new(config: Option<WakuNodeConfig>) -> Result<WakuNodeContext> {
+    let config = config.unwrap_or_default();
+    let (obj_ptr, response) = waku_immediate_request(new_request(&config)?);
+    handle_new_response(obj_ptr, config, response)
+}
+
+/// Free the resources held by a Waku node
+pub async fn waku_destroy(ctx: &WakuNodeContext) -> Result<()> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_destroy(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
 
-    handle_no_response(code, result)
+    handle_no_response(result?)
 }
 
 /// Start a Waku node mounting all the protocols that were enabled during the Waku node instantiation.
 /// as per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_start)
-pub fn waku_start(ctx: &WakuNodeContext) -> Result<()> {
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        waku_sys::waku_start(ctx.obj_ptr, cb, &mut closure as *mut _ as *mut c_void)
-    };
+pub async fn waku_start(ctx: &WakuNodeContext) -> Result<()> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_start(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
 
-    handle_no_response(code, result)
+    handle_no_response(result?)?;
+    ctx.set_running(true);
+    Ok(())
 }
 
 /// Stops a Waku node
 /// as per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_stop)
-pub fn waku_stop(ctx: &WakuNodeContext) -> Result<()> {
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        waku_sys::waku_stop(ctx.obj_ptr, cb, &mut closure as *mut _ as *mut c_void)
-    };
+pub async fn waku_stop(ctx: &WakuNodeContext) -> Result<()> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_stop(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
 
-    handle_no_response(code, result)
+    handle_no_response(result?)?;
+    ctx.set_running(false);
+    Ok(())
+}
+
+/// Blocking version of [`waku_stop`], for when the node is dropped
+pub(crate) fn waku_stop_blocking(ctx: &WakuNodeContext) -> Result<()> {
+    let result = waku_request(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_stop(ctx.obj_ptr, cb, user_data)
+    });
+
+    handle_no_response(result?)?;
+    ctx.set_running(false);
+    Ok(())
+}
+
+/// Blocking version of [`waku_destroy`], for when the node is dropped
+pub(crate) fn waku_destroy_blocking(ctx: &WakuNodeContext) -> Result<()> {
+    let result = waku_request(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_destroy(ctx.obj_ptr, cb, user_data)
+    });
+
+    handle_no_response(result?)
 }
 
 /// nwaku version
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub fn waku_version(ctx: &WakuNodeContext) -> Result<String> {
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        waku_sys::waku_version(ctx.obj_ptr, cb, &mut closure as *mut _ as *mut c_void)
-    };
+pub async fn waku_version(ctx: &WakuNodeContext) -> Result<String> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_version(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
 
-    handle_response(code, result)
+    handle_response(result?)
 }
 
 // Implement WakuDecode for Vec<Multiaddr>
@@ -106,24 +144,110 @@ impl WakuDecode for Vec<Multiaddr> {
     fn decode(input: &str) -> Result<Self> {
         input
             .split(',')
-            .map(|s| s.trim().parse::<Multiaddr>().map_err(|err| err.to_string()))
+            .map(|s| {
+                s.trim()
+                    .parse::<Multiaddr>()
+                    .map_err(|err| WakuError::Decode(format!("could not parse Multiaddr: {}", err)))
+            })
             .collect::<Result<Vec<Multiaddr>>>() // Collect results into a Vec
-            .map_err(|err| format!("could not parse Multiaddr: {}", err))
     }
 }
 
 /// Get the multiaddresses the Waku node is listening to
 /// as per [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_listen_addresses)
-pub fn waku_listen_addresses(ctx: &WakuNodeContext) -> Result<Vec<Multiaddr>> {
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        waku_sys::waku_listen_addresses(ctx.obj_ptr, cb, &mut closure as *mut _ as *mut c_void)
-    };
+pub async fn waku_listen_addresses(ctx: &WakuNodeContext) -> Result<Vec<Multiaddr>> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_listen_addresses(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
+
+    handle_json_response(result?)
+}
+
+/// Get the node record the node advertises for discovery
+pub async fn waku_my_enr(ctx: &WakuNodeContext) -> Result<Enr> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_get_my_enr(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
+
+    handle_response(result?)
+}
 
-    handle_json_response(code, result)
+/// Get the libp2p peer id of the node
+pub async fn waku_my_peer_id(ctx: &WakuNodeContext) -> Result<PeerId> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_get_my_peerid(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
+
+    handle_response(result?)
+}
+
+/// Snapshot of a running node identity and settings
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    /// nwaku version
+    pub version: String,
+    /// Node record advertised for discovery
+    pub enr: Enr,
+    /// Libp2p peer id
+    pub peer_id: PeerId,
+    /// Multiaddresses the node is listening to
+    pub listen_addresses: Vec<Multiaddr>,
+    /// Ids of the protocols enabled in the node configuration,
+    /// libwaku does not report the protocols it mounted
+    pub configured_protocols: Vec<String>,
+    /// Cluster the node advertises in its record
+    pub cluster_id: Option<usize>,
+    /// Shards the node advertises in its record
+    pub shards: Vec<usize>,
+    /// Pubsub topics the node is relay subscribed to
+    pub relay_subscriptions: Vec<String>,
+}
+
+impl NodeInfo {
+    fn new(
+        ctx: &WakuNodeContext,
+        version: String,
+        enr: Enr,
+        peer_id: PeerId,
+        listen_addresses: Vec<Multiaddr>,
+    ) -> Result<Self> {
+        let relay_shards = WakuEnr::from(enr.clone()).relay_shards()?;
+        Ok(Self {
+            version,
+            enr,
+            peer_id,
+            listen_addresses,
+            configured_protocols: ctx.config().protocols(),
+            cluster_id: relay_shards
+                .as_ref()
+                .map(|relay_shards| relay_shards.cluster_id.into()),
+            shards: relay_shards
+                .map(|relay_shards| relay_shards.shards.into_iter().map(usize::from).collect())
+                .unwrap_or_default(),
+            relay_subscriptions: ctx
+                .relay_subscriptions()
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .cloned()
+                .collect(),
+        })
+    }
+}
+
+/// Gather the node identity and settings
+pub async fn waku_node_info(ctx: &WakuNodeContext) -> Result<NodeInfo> {
+    NodeInfo::new(
+        ctx,
+        waku_version(ctx).await?,
+        waku_my_enr(ctx).await?,
+        waku_my_peer_id(ctx).await?,
+        waku_listen_addresses(ctx).await?,
+    )
 }
 
 #[cfg(test)]
@@ -132,26 +256,28 @@ mod test {
     use crate::node::management::{waku_listen_addresses, waku_start, waku_stop, waku_version};
     use serial_test::serial;
 
-    #[test]
+    #[tokio::test]
     #[serial]
-    fn waku_flow() {
+    async fn waku_flow() {
         let node = waku_new(None).unwrap();
 
-        waku_start(&node).unwrap();
+        waku_start(&node).await.unwrap();
 
         // test addresses
-        let addresses = waku_listen_addresses(&node).unwrap();
+        let addresses = waku_listen_addresses(&node).await.unwrap();
         dbg!(&addresses);
         assert!(!addresses.is_empty());
 
-        waku_stop(&node).unwrap();
+        waku_stop(&node).await.unwrap();
     }
 
-    #[test]
+    #[tokio::test]
     #[serial]
-    fn nwaku_version() {
+    async fn nwaku_version() {
         let node = waku_new(None).unwrap();
-        let version = waku_version(&node).expect("should return the version");
+        let version = waku_version(&node)
+            .await
+            .expect("should return the version");
         assert!(!version.is_empty());
     }
 }
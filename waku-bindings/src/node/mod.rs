@@ -1,25 +1,49 @@
 //! Waku node implementation
 
 mod config;
+mod discovery;
 mod events;
+mod filter;
+mod latency;
+mod lightpush;
 mod management;
 mod peers;
 mod relay;
+mod store;
+mod waku_enr;
 
 // std
 pub use aes_gcm::Key;
+use futures::stream::{self, BoxStream};
+use futures::{future, StreamExt};
 pub use multiaddr::Multiaddr;
 pub use secp256k1::{PublicKey, SecretKey};
+/// Ethereum Node Record, as advertised by Waku nodes for discovery
+pub type Enr = enr::Enr<enr::secp256k1::SecretKey>;
+use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::Duration;
 // internal
-use crate::general::{MessageHash, Result, WakuMessage};
+use crate::general::{MessageHash, Result, WakuContentTopic, WakuError, WakuMessage};
 
 pub use config::RLNConfig;
-pub use config::WakuNodeConfig;
-pub use events::{Event, WakuMessageEvent, WakuNodeContext};
-pub use relay::waku_create_content_topic;
+pub use config::{LogLevel, RetentionPolicy, WakuNodeConfig, WakuNodeConfigBuilder};
+pub use discovery::{DnsInfo, DEFAULT_NAMESERVER, PEER_EXCHANGE_PROTOCOL};
+pub use events::{
+    Event, EventStreamConfig, MessageProtocol, OverflowPolicy, WakuEventStream, WakuMessageEvent,
+    WakuNodeContext,
+};
+pub use filter::FILTER_SUBSCRIBE_PROTOCOL;
+pub use latency::{LatencyTrackerConfig, PeerLatency, PeerLatencyTracker};
+pub use lightpush::LIGHTPUSH_PROTOCOL;
+pub use management::NodeInfo;
+pub use peers::{ConnectionDirection, PeerAddress, PeerId, PeerInfo};
+pub use relay::{waku_create_content_topic, RELAY_PROTOCOL};
+pub use store::{PagingDirection, StoreQuery, StoredMessage, STORE_QUERY_PROTOCOL};
+pub use waku_enr::{RelayShards, WakuCapabilities, WakuEnr, WakuEnrBuilder};
 
-use crate::WakuContentTopic;
+use crate::utils::LibwakuResponse;
 use crate::Encoding;
 use std::time::SystemTime;
 
@@ -36,61 +60,236 @@ impl WakuNodeState for Initialized {}
 impl WakuNodeState for Running {}
 
 /// Handle to the underliying waku node
-pub struct WakuNodeHandle {
-    pub ctx: WakuNodeContext,
+///
+/// The handle is cheap to clone and can be shared between threads and tasks:
+/// libwaku serializes the requests sent to a node on the node's own thread.
+/// All clones refer to the same node, so a state transition made through one of them
+/// (e.g. [`WakuNodeHandle::stop`]) also applies to the node the others point to:
+/// once the node is stopped, the requests made through a `Running` clone fail with [`WakuError::NotRunning`].
+/// Once the last clone is dropped the node is stopped, if running, and destroyed,
+/// errors being reported to the hook registered with [`crate::set_callback_error_hook`];
+/// use [`WakuNodeHandle::shutdown`] to get them instead.
+/// Dropping the last clone blocks the current thread until libwaku answers, which can take
+/// up to a minute for each of the two requests: inside an async runtime, prefer
+/// [`WakuNodeHandle::shutdown`] or drop it from [`tokio::task::spawn_blocking`].
+/// A handle must not be moved into its own node event callback, the node would keep itself alive.
+pub struct WakuNodeHandle<State: WakuNodeState> {
+    ctx: Arc<WakuNodeContext>,
+    _state: PhantomData<State>,
+}
+
+impl<State: WakuNodeState> Clone for WakuNodeHandle<State> {
+    fn clone(&self) -> Self {
+        Self {
+            ctx: Arc::clone(&self.ctx),
+            _state: PhantomData,
+        }
+    }
 }
 
 /// Spawn a new Waku node with the given configuration (default configuration if `None` provided)
 /// as per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_newchar-jsonconfig)
-pub fn waku_new(config: Option<WakuNodeConfig>) -> Result<WakuNodeHandle> {
+pub fn waku_new(config: Option<WakuNodeConfig>) -> Result<WakuNodeHandle<Initialized>> {
     Ok(WakuNodeHandle {
-        ctx: management::waku_new(config)?,
+        ctx: Arc::new(management::waku_new(config)?),
+        _state: PhantomData,
     })
 }
 
-pub fn waku_destroy(node: WakuNodeHandle) -> Result<()> {
-    management::waku_destroy(&node.ctx)
+/// Free the resources held by a stopped Waku node.
+/// If other clones of the handle are still alive, the node is destroyed when the last of them is dropped.
+pub async fn waku_destroy(node: WakuNodeHandle<Initialized>) -> Result<()> {
+    node.shutdown().await
 }
 
-// unsafe impl Send for WakuNodeHandle<Running> {}
+impl<State: WakuNodeState> WakuNodeHandle<State> {
+    /// Stop the node if it is running and destroy it, returning the first error met.
+    /// Dropping the last handle does the same but can only report the errors to the callback error hook.
+    /// If other clones of the handle are still alive the node is only stopped,
+    /// it is destroyed once the last of them is dropped.
+    pub async fn shutdown(self) -> Result<()> {
+        match Arc::try_unwrap(self.ctx) {
+            Ok(mut ctx) => ctx.shutdown().await,
+            Err(ctx) if ctx.is_running() => management::waku_stop(&ctx).await,
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Get the nwaku version
+    pub async fn version(&self) -> Result<String> {
+        management::waku_version(&self.ctx).await
+    }
+
+    /// Register callback to act as event handler and receive application events,
+    /// which are used to react to asynchronous events in Waku
+    pub fn set_event_callback<F: FnMut(LibwakuResponse) + 'static + Sync + Send>(
+        &self,
+        closure: F,
+    ) {
+        self.ctx.waku_set_event_callback(closure)
+    }
+
+    /// Stream of the node [`Event`]s, using the default [`EventStreamConfig`].
+    /// Replaces any event callback previously registered on this node.
+    pub fn events(&self) -> WakuEventStream {
+        self.events_with_config(EventStreamConfig::default())
+    }
 
-impl WakuNodeHandle {
+    /// Stream of the node [`Event`]s, buffered as per `config`.
+    /// Replaces any event callback previously registered on this node.
+    pub fn events_with_config(&self, config: EventStreamConfig) -> WakuEventStream {
+        self.ctx.waku_event_stream(config)
+    }
+}
+
+impl WakuNodeHandle<Initialized> {
     /// Start a Waku node mounting all the protocols that were enabled during the Waku node instantiation.
     /// as per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_start)
-    pub fn start(&self) -> Result<()> {
-        management::waku_start(&self.ctx)
+    pub async fn start(self) -> Result<WakuNodeHandle<Running>> {
+        management::waku_start(&self.ctx).await?;
+        Ok(WakuNodeHandle {
+            ctx: self.ctx,
+            _state: PhantomData,
+        })
+    }
+}
+
+impl WakuNodeHandle<Running> {
+    /// Context of the node, unless it was stopped through another clone of the handle
+    fn running_ctx(&self) -> Result<&Arc<WakuNodeContext>> {
+        if self.ctx.is_running() {
+            Ok(&self.ctx)
+        } else {
+            Err(WakuError::NotRunning)
+        }
     }
 
     /// Stops a Waku node
     /// as per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_stop)
-    pub fn stop(&self) -> Result<()> {
-        management::waku_stop(&self.ctx)
+    pub async fn stop(self) -> Result<WakuNodeHandle<Initialized>> {
+        management::waku_stop(self.running_ctx()?).await?;
+        Ok(WakuNodeHandle {
+            ctx: self.ctx,
+            _state: PhantomData,
+        })
     }
 
     /// Get the multiaddresses the Waku node is listening to
     /// as per [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_listen_addresses)
-    pub fn listen_addresses(&self) -> Result<Vec<Multiaddr>> {
-        management::waku_listen_addresses(&self.ctx)
+    pub async fn listen_addresses(&self) -> Result<Vec<Multiaddr>> {
+        management::waku_listen_addresses(self.running_ctx()?).await
     }
 
-    /// Get the nwaku version
-    pub fn version(&self) -> Result<String> {
-        management::waku_version(&self.ctx)
+    /// Get the node record the Waku node advertises for discovery
+    pub async fn my_enr(&self) -> Result<Enr> {
+        management::waku_my_enr(self.running_ctx()?).await
+    }
+
+    /// Get the libp2p peer id of the Waku node
+    pub async fn my_peer_id(&self) -> Result<PeerId> {
+        management::waku_my_peer_id(self.running_ctx()?).await
+    }
+
+    /// Snapshot of the Waku node identity, listen addresses, shards and relay subscriptions.
+    /// Shards are read from the node record, protocols from the node configuration.
+    pub async fn node_info(&self) -> Result<NodeInfo> {
+        management::waku_node_info(self.running_ctx()?).await
     }
 
-    /// Dial peer using a multiaddress
+    /// Dial a peer given one of its addresses, its record, or its peer id if it is in the peerstore,
+    /// negotiating `protocols` with it, e.g. [`RELAY_PROTOCOL`], or only connecting if `None`.
+    /// A peer id given without `protocols` is dialed on [`RELAY_PROTOCOL`].
+    /// The addresses of a record are tried in order until one of them succeeds.
     /// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
     /// If the function execution takes longer than `timeout` value, the execution will be canceled and an error returned.
     /// Use 0 for no timeout
     /// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_connect_peerchar-address-int-timeoutms)
-    pub fn connect(&self, address: &Multiaddr, timeout: Option<Duration>) -> Result<()> {
-        peers::waku_connect(&self.ctx, address, timeout)
+    pub async fn connect(
+        &self,
+        peer: impl Into<PeerAddress>,
+        protocols: Option<&[&str]>,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        peers::waku_connect_peer(
+            self.running_ctx()?,
+            &peer.into(),
+            protocols.unwrap_or_default(),
+            timeout,
+        )
+        .await
+    }
+
+    /// Peers the node is currently connected to
+    pub async fn connected_peers(&self) -> Result<Vec<PeerInfo>> {
+        peers::waku_connected_peers(self.running_ctx()?).await
+    }
+
+    /// Ids of the peers stored in the node peerstore, connected or not
+    pub async fn peerstore_peers(&self) -> Result<Vec<PeerId>> {
+        peers::waku_peerstore_peers(self.running_ctx()?).await
+    }
+
+    /// Close the connections to a peer
+    pub async fn disconnect(&self, peer_id: &PeerId) -> Result<()> {
+        peers::waku_disconnect(self.running_ctx()?, peer_id).await
+    }
+
+    /// Add a peer to the peerstore as serving `protocol`, e.g. [`crate::LIGHTPUSH_PROTOCOL`],
+    /// without connecting to it
+    pub async fn add_peer(&self, address: &Multiaddr, protocol: &str) -> Result<()> {
+        peers::waku_add_peer(self.running_ctx()?, address, protocol).await
+    }
+
+    /// Start discv5, which requires the node to be created with [`WakuNodeConfig::discv5_discovery`] enabled
+    pub async fn start_discv5(&self) -> Result<()> {
+        discovery::waku_start_discv5(self.running_ctx()?).await
+    }
+
+    /// Stop discv5, the peers already found are kept
+    pub async fn stop_discv5(&self) -> Result<()> {
+        discovery::waku_stop_discv5(self.running_ctx()?).await
+    }
+
+    /// Replace the nodes discv5 bootstraps from, without restarting the node
+    pub async fn update_discv5_bootnodes(&self, bootnodes: Vec<Enr>) -> Result<()> {
+        discovery::waku_discv5_update_bootnodes(self.running_ctx()?, &bootnodes).await
+    }
+
+    /// Resolve the peers published in the `enrtree://` tree at `enrtree_url`,
+    /// querying `nameserver`, [`DEFAULT_NAMESERVER`] if `None`.
+    /// Unlike [`WakuNodeConfig::dns_discovery_url`], the found peers are not dialed.
+    /// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
+    pub async fn dns_discovery(
+        &self,
+        enrtree_url: &str,
+        nameserver: Option<IpAddr>,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<DnsInfo>> {
+        discovery::waku_dns_discovery(self.running_ctx()?, enrtree_url, nameserver, timeout).await
+    }
+
+    /// Ask the peer exchange service node `peer` for up to `num_peers` of its peers,
+    /// returning their records and adding them to the peerstore.
+    /// The service node has to be created with [`WakuNodeConfig::peer_exchange`] enabled.
+    pub async fn peer_exchange_request(
+        &self,
+        num_peers: usize,
+        peer: &Multiaddr,
+    ) -> Result<Vec<Enr>> {
+        discovery::waku_peer_exchange_request(self.running_ctx()?, num_peers, peer).await
+    }
+
+    /// Ping a peer using the libp2p ping protocol, returning the round trip time.
+    /// See [`PeerLatencyTracker`] to keep track of the latency of several peers.
+    /// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
+    pub async fn ping(&self, peer: &Multiaddr, timeout: Option<Duration>) -> Result<Duration> {
+        peers::waku_ping_peer(self.running_ctx()?, peer, timeout).await
     }
 
-    pub fn relay_publish_txt(
+    pub async fn relay_publish_txt(
         &self,
-        pubsub_topic: &String,
-        msg_txt: &String,
+        pubsub_topic: &str,
+        msg_txt: &str,
         content_topic_name: &'static str,
         timeout: Option<Duration>,
     ) -> Result<MessageHash> {
@@ -109,29 +308,102 @@ impl WakuNodeHandle {
             false,
         );
 
-        relay::waku_relay_publish_message(&self.ctx, &message, pubsub_topic, timeout)
+        relay::waku_relay_publish_message(self.running_ctx()?, &message, pubsub_topic, timeout)
+            .await
     }
 
     /// Publish a message using Waku Relay.
     /// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_relay_publishchar-messagejson-char-pubsubtopic-int-timeoutms)
     /// The pubsub_topic parameter is optional and if not specified it will be derived from the contentTopic.
-    pub fn relay_publish_message(
+    pub async fn relay_publish_message(
         &self,
         message: &WakuMessage,
-        pubsub_topic: &String,
+        pubsub_topic: &str,
         timeout: Option<Duration>,
     ) -> Result<MessageHash> {
-        relay::waku_relay_publish_message(&self.ctx, message, pubsub_topic, timeout)
+        relay::waku_relay_publish_message(self.running_ctx()?, message, pubsub_topic, timeout).await
     }
 
     /// Subscribe to WakuRelay to receive messages matching a content filter.
-    pub fn relay_subscribe(&self, pubsub_topic: &String) -> Result<()> {
-        relay::waku_relay_subscribe(&self.ctx, pubsub_topic)
+    pub async fn relay_subscribe(&self, pubsub_topic: &str) -> Result<()> {
+        relay::waku_relay_subscribe(self.running_ctx()?, pubsub_topic).await
     }
 
     /// Closes the pubsub subscription to stop receiving messages matching a content filter. No more messages will be received from this pubsub topic
-    pub fn relay_unsubscribe(&self, pubsub_topic: &String) -> Result<()> {
-        relay::waku_relay_unsubscribe(&self.ctx, pubsub_topic)
+    pub async fn relay_unsubscribe(&self, pubsub_topic: &str) -> Result<()> {
+        relay::waku_relay_unsubscribe(self.running_ctx()?, pubsub_topic).await
+    }
+
+    /// Publish a message through a lightpush service node, returning the message hash.
+    /// Without `peer`, libwaku picks one of the known lightpush service nodes,
+    /// otherwise `peer` must end with its `/p2p/` peer id.
+    /// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_lightpush_publishchar-messagejson-char-topic-char-peerid-int-timeoutms)
+    pub async fn lightpush_publish(
+        &self,
+        message: &WakuMessage,
+        pubsub_topic: &str,
+        peer: Option<&Multiaddr>,
+    ) -> Result<MessageHash> {
+        lightpush::waku_lightpush_publish_message(self.running_ctx()?, message, pubsub_topic, peer)
+            .await
+    }
+
+    /// Run `query` against the store node `peer`, following the pagination cursors
+    /// as the returned stream is consumed. `timeout` applies to each page request.
+    /// As per the [specification](https://rfc.vac.dev/spec/13/)
+    pub fn store_query_stream(
+        &self,
+        query: StoreQuery,
+        peer: &Multiaddr,
+        timeout: Option<Duration>,
+    ) -> BoxStream<'static, Result<StoredMessage>> {
+        match self.running_ctx() {
+            Ok(ctx) => store::waku_store_messages(Arc::clone(ctx), query, peer.clone(), timeout),
+            Err(e) => stream::once(future::ready(Err(e))).boxed(),
+        }
+    }
+
+    /// Run `query` against the store node `peer`, collecting the messages of every page
+    pub async fn store_query(
+        &self,
+        query: StoreQuery,
+        peer: &Multiaddr,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<StoredMessage>> {
+        let ctx = Arc::clone(self.running_ctx()?);
+        store::waku_store_all_messages(ctx, query, peer.clone(), timeout).await
     }
 
+    /// Subscribe to messages matching `content_topics` on `pubsub_topic`, pushed by the filter service node `peer`.
+    /// The messages are delivered as [`Event::WakuMessage`] with [`MessageProtocol::Filter`].
+    /// As per the [specification](https://rfc.vac.dev/spec/12/#subscribe)
+    pub async fn filter_subscribe(
+        &self,
+        peer: &Multiaddr,
+        pubsub_topic: &str,
+        content_topics: &[WakuContentTopic],
+    ) -> Result<()> {
+        filter::waku_filter_subscribe(self.running_ctx()?, peer, pubsub_topic, content_topics).await
+    }
+
+    /// Check that the filter service node `peer` still holds the subscriptions of this node
+    pub async fn filter_ping(&self, peer: &Multiaddr) -> Result<()> {
+        filter::waku_filter_ping(self.running_ctx()?, peer).await
+    }
+
+    /// Stop receiving messages matching `content_topics` on `pubsub_topic` through filter
+    /// As per the [specification](https://rfc.vac.dev/spec/12/#unsubscribe)
+    pub async fn filter_unsubscribe(
+        &self,
+        pubsub_topic: &str,
+        content_topics: &[WakuContentTopic],
+    ) -> Result<()> {
+        filter::waku_filter_unsubscribe(self.running_ctx()?, pubsub_topic, content_topics).await
+    }
+
+    /// Stop receiving any message through filter
+    /// As per the [specification](https://rfc.vac.dev/spec/12/#unsubscribe-all)
+    pub async fn filter_unsubscribe_all(&self) -> Result<()> {
+        filter::waku_filter_unsubscribe_all(self.running_ctx()?).await
+    }
 }
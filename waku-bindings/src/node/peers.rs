@@ -2,49 +2,734 @@
 
 // std
 use std::ffi::CString;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
 use std::time::Duration;
 // crates
 use libc::*;
-use multiaddr::Multiaddr;
+use multiaddr::multihash::Multihash;
+use multiaddr::{Multiaddr, Protocol};
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use waku_sys::WakuCallBack;
 // internal
-use crate::general::Result;
-use crate::utils::LibwakuResponse;
-use crate::utils::{get_trampoline, handle_no_response};
+use crate::general::{Result, WakuError};
 use crate::node::events::WakuNodeContext;
+use crate::node::relay::RELAY_PROTOCOL;
+use crate::node::waku_enr::WakuEnr;
+use crate::node::Enr;
+use crate::utils::{
+    handle_json_response, handle_no_response, request_deadline, timeout_millis, to_c_string,
+    waku_request_async, WakuDecode, DEFAULT_REQUEST_DEADLINE,
+};
+
+/// Multihash code of the identity hash, used for public keys of up to 42 bytes
+const IDENTITY_CODE: u64 = 0x00;
+/// Multihash code of sha2-256, used for larger public keys
+const SHA2_256_CODE: u64 = 0x12;
+
+/// Libp2p peer id, the multihash of the peer's protobuf encoded public key
+/// As per the [specification](https://github.com/libp2p/specs/blob/master/peer-ids/peer-ids.md)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeerId(Multihash);
+
+impl PeerId {
+    /// Peer id of the node owning `public_key`, as derived by libp2p for secp256k1 keys
+    pub fn from_public_key(public_key: &PublicKey) -> Self {
+        // protobuf `PublicKey { Type: Secp256k1 = 2, Data: <33 bytes compressed key> }`
+        let mut encoded = vec![0x08, 0x02, 0x12, 0x21];
+        encoded.extend_from_slice(&public_key.serialize());
+        Self(
+            Multihash::wrap(IDENTITY_CODE, &encoded)
+                .expect("An encoded secp256k1 public key fits into an identity multihash"),
+        )
+    }
+
+    /// Peer id of a node using `secret_key` as its node key
+    pub fn from_secret_key(secret_key: &SecretKey) -> Self {
+        Self::from_public_key(&PublicKey::from_secret_key(
+            &Secp256k1::signing_only(),
+            secret_key,
+        ))
+    }
+
+    /// Peer id found in the last `/p2p/` component of `address`, if any
+    pub fn from_multiaddr(address: &Multiaddr) -> Option<Self> {
+        address
+            .iter()
+            .fold(None, |peer_id, protocol| match protocol {
+                Protocol::P2p(multihash) => Self::try_from(multihash).ok(),
+                _ => peer_id,
+            })
+    }
+
+    /// Peer id ending `address`, needed by the libwaku requests sent to a given peer
+    pub(crate) fn from_dialable(address: &Multiaddr) -> Result<Self> {
+        match address.iter().last() {
+            Some(Protocol::P2p(multihash)) => Self::try_from(multihash),
+            _ => Err(WakuError::InvalidPeerId(format!(
+                "{address} does not end with a /p2p/ component"
+            ))),
+        }
+    }
+
+    /// Peer id from its binary multihash representation
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Multihash::from_bytes(bytes)
+            .map_err(|e| WakuError::InvalidPeerId(e.to_string()))?
+            .try_into()
+    }
+
+    /// Binary multihash representation of the peer id
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+}
+
+impl From<&Enr> for PeerId {
+    fn from(enr: &Enr) -> Self {
+        Self::from_public_key(
+            &PublicKey::from_slice(&enr.public_key().serialize())
+                .expect("An ENR always holds a valid secp256k1 public key"),
+        )
+    }
+}
+
+impl TryFrom<Multihash> for PeerId {
+    type Error = WakuError;
+
+    fn try_from(multihash: Multihash) -> Result<Self> {
+        match multihash.code() {
+            IDENTITY_CODE => Ok(Self(multihash)),
+            SHA2_256_CODE if multihash.size() == 32 => Ok(Self(multihash)),
+            code => Err(WakuError::InvalidPeerId(format!(
+                "unsupported multihash code {code:#x} of size {}",
+                multihash.size()
+            ))),
+        }
+    }
+}
+
+impl From<PeerId> for Protocol<'_> {
+    fn from(peer_id: PeerId) -> Self {
+        Protocol::P2p(peer_id.0)
+    }
+}
+
+impl FromStr for PeerId {
+    type Err = WakuError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = bs58::decode(s)
+            .into_vec()
+            .map_err(|e| WakuError::InvalidPeerId(format!("{s}: {e}")))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl Display for PeerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&bs58::encode(self.to_bytes()).into_string())
+    }
+}
+
+impl Debug for PeerId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PeerId").field(&self.to_string()).finish()
+    }
+}
+
+impl Serialize for PeerId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PeerId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Direction of the connection to a peer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionDirection {
+    /// The peer dialed this node
+    Inbound,
+    /// This node dialed the peer
+    Outbound,
+}
+
+/// Peer the node is connected to
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerInfo {
+    /// Libp2p peer id
+    pub peer_id: PeerId,
+    /// Addresses the peer can be reached at
+    pub addresses: Vec<Multiaddr>,
+    /// Protocols the peer supports
+    pub protocols: Vec<String>,
+    /// Direction of the connection to the peer
+    pub direction: ConnectionDirection,
+    /// Shards the peer relays
+    pub shards: Vec<usize>,
+}
+
+impl WakuDecode for Vec<PeerInfo> {
+    fn decode(input: &str) -> Result<Self> {
+        Ok(serde_json::from_str(input)?)
+    }
+}
+
+// The peerstore peers are answered as comma separated peer ids
+impl WakuDecode for Vec<PeerId> {
+    fn decode(input: &str) -> Result<Self> {
+        input
+            .split(',')
+            .filter(|peer_id| !peer_id.is_empty())
+            .map(|peer_id| {
+                peer_id
+                    .parse()
+                    .map_err(|e: WakuError| WakuError::Decode(e.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// Peer to dial: one of its addresses, its record, or its peer id when it is in the peerstore
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PeerAddress {
+    Multiaddr(Multiaddr),
+    Enr(Enr),
+    PeerId(PeerId),
+}
+
+impl From<Multiaddr> for PeerAddress {
+    fn from(address: Multiaddr) -> Self {
+        PeerAddress::Multiaddr(address)
+    }
+}
+
+impl From<&Multiaddr> for PeerAddress {
+    fn from(address: &Multiaddr) -> Self {
+        PeerAddress::Multiaddr(address.clone())
+    }
+}
+
+impl From<Enr> for PeerAddress {
+    fn from(enr: Enr) -> Self {
+        PeerAddress::Enr(enr)
+    }
+}
+
+impl From<&Enr> for PeerAddress {
+    fn from(enr: &Enr) -> Self {
+        PeerAddress::Enr(enr.clone())
+    }
+}
+
+impl From<WakuEnr> for PeerAddress {
+    fn from(enr: WakuEnr) -> Self {
+        PeerAddress::Enr(enr.into_enr())
+    }
+}
+
+impl From<PeerId> for PeerAddress {
+    fn from(peer_id: PeerId) -> Self {
+        PeerAddress::PeerId(peer_id)
+    }
+}
+
+impl From<&PeerId> for PeerAddress {
+    fn from(peer_id: &PeerId) -> Self {
+        PeerAddress::PeerId(*peer_id)
+    }
+}
+
+// `enr:` records, `/` rooted multiaddresses, otherwise peer ids
+impl FromStr for PeerAddress {
+    type Err = WakuError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.starts_with("enr:") {
+            Enr::from_str(s)
+                .map(PeerAddress::Enr)
+                .map_err(WakuError::InvalidEnr)
+        } else if s.starts_with('/') {
+            Multiaddr::from_str(s)
+                .map(PeerAddress::Multiaddr)
+                .map_err(|e| WakuError::Decode(format!("invalid multiaddr {s}: {e}")))
+        } else {
+            PeerId::from_str(s).map(PeerAddress::PeerId)
+        }
+    }
+}
+
+fn connect_request<'a>(
+    ctx: &'a WakuNodeContext,
+    address: &'a Multiaddr,
+    timeout: Option<Duration>,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let address = to_c_string(address.to_string(), WakuError::InvalidArgument)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let address_ptr = address.into_raw();
+
+        let out = waku_sys::waku_connect(
+            ctx.obj_ptr,
+            address_ptr,
+            timeout_millis(timeout) as c_uint,
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(address_ptr));
+
+        out
+    })
+}
 
 /// Dial peer using a multiaddress
 /// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
 /// If the function execution takes longer than `timeout` value, the execution will be canceled and an error returned.
 /// Use 0 for no timeout
 /// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_connect_peerchar-address-int-timeoutms)
-pub fn waku_connect(
+pub async fn waku_connect(
     ctx: &WakuNodeContext,
     address: &Multiaddr,
     timeout: Option<Duration>,
 ) -> Result<()> {
-    let address_ptr = CString::new(address.to_string())
-        .expect("CString should build properly from multiaddress")
-        .into_raw();
-
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        let out = waku_sys::waku_connect(
+    let result = waku_request_async(
+        request_deadline(timeout),
+        connect_request(ctx, address, timeout)?,
+    )
+    .await;
+
+    handle_no_response(result?)
+}
+
+fn dial_peer_request<'a>(
+    ctx: &'a WakuNodeContext,
+    address: &'a Multiaddr,
+    protocol: &'a str,
+    timeout: Option<Duration>,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let address = to_c_string(address.to_string(), WakuError::InvalidArgument)?;
+    let protocol = to_c_string(protocol, WakuError::InvalidArgument)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let address_ptr = address.into_raw();
+        let protocol_ptr = protocol.into_raw();
+
+        let out = waku_sys::waku_dial_peer(
             ctx.obj_ptr,
             address_ptr,
-            timeout
-                .map(|duration| duration.as_millis().try_into().unwrap_or(u32::MAX))
-                .unwrap_or(0),
+            protocol_ptr,
+            timeout_millis(timeout),
             cb,
-            &mut closure as *mut _ as *mut c_void,
+            user_data,
         );
 
         drop(CString::from_raw(address_ptr));
+        drop(CString::from_raw(protocol_ptr));
+
+        out
+    })
+}
+
+/// Dial peer using a multiaddress, negotiating `protocol` with it
+/// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
+pub async fn waku_dial_peer(
+    ctx: &WakuNodeContext,
+    address: &Multiaddr,
+    protocol: &str,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let result = waku_request_async(
+        request_deadline(timeout),
+        dial_peer_request(ctx, address, protocol, timeout)?,
+    )
+    .await;
+
+    handle_no_response(result?)
+}
+
+fn dial_peer_by_id_request<'a>(
+    ctx: &'a WakuNodeContext,
+    peer_id: &'a PeerId,
+    protocol: &'a str,
+    timeout: Option<Duration>,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let peer_id = to_c_string(peer_id.to_string(), WakuError::InvalidPeerId)?;
+    let protocol = to_c_string(protocol, WakuError::InvalidArgument)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let peer_id_ptr = peer_id.into_raw();
+        let protocol_ptr = protocol.into_raw();
+
+        let out = waku_sys::waku_dial_peer_by_id(
+            ctx.obj_ptr,
+            peer_id_ptr,
+            protocol_ptr,
+            timeout_millis(timeout),
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(peer_id_ptr));
+        drop(CString::from_raw(protocol_ptr));
 
         out
+    })
+}
+
+/// Dial a peer of the peerstore using its peer id, negotiating `protocol` with it
+/// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
+pub async fn waku_dial_peer_by_id(
+    ctx: &WakuNodeContext,
+    peer_id: &PeerId,
+    protocol: &str,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let result = waku_request_async(
+        request_deadline(timeout),
+        dial_peer_by_id_request(ctx, peer_id, protocol, timeout)?,
+    )
+    .await;
+
+    handle_no_response(result?)
+}
+
+/// Addresses to dial the node of `enr` at
+fn enr_addresses(enr: &Enr) -> Result<Vec<Multiaddr>> {
+    Some(WakuEnr::from(enr.clone()).addresses())
+        .filter(|addresses| !addresses.is_empty())
+        .ok_or_else(|| WakuError::InvalidEnr(format!("{enr} holds no dialable address")))
+}
+
+/// Dial `address` negotiating each of `protocols`
+async fn dial_protocols(
+    ctx: &WakuNodeContext,
+    address: &Multiaddr,
+    protocols: &[&str],
+    timeout: Option<Duration>,
+) -> Result<()> {
+    for protocol in protocols {
+        waku_dial_peer(ctx, address, protocol, timeout).await?;
+    }
+    Ok(())
+}
+
+/// Dial `peer`, negotiating `protocols` with it or only connecting if there are none.
+/// The peer addresses are tried in order until one of them succeeds.
+/// libwaku only dials peer ids on a protocol, so they are dialed on [`RELAY_PROTOCOL`] without `protocols`.
+/// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
+pub async fn waku_connect_peer(
+    ctx: &WakuNodeContext,
+    peer: &PeerAddress,
+    protocols: &[&str],
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let addresses = match peer {
+        PeerAddress::Multiaddr(address) => vec![address.clone()],
+        PeerAddress::Enr(enr) => enr_addresses(enr)?,
+        PeerAddress::PeerId(peer_id) => {
+            let protocols = if protocols.is_empty() {
+                &[RELAY_PROTOCOL]
+            } else {
+                protocols
+            };
+            for protocol in protocols {
+                waku_dial_peer_by_id(ctx, peer_id, protocol, timeout).await?;
+            }
+            return Ok(());
+        }
     };
 
-    handle_no_response(code, result)
+    let mut result = Ok(());
+    for address in addresses {
+        result = if protocols.is_empty() {
+            waku_connect(ctx, &address, timeout).await
+        } else {
+            dial_protocols(ctx, &address, protocols, timeout).await
+        };
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+// Ping round trip times are reported in nanoseconds
+impl WakuDecode for Duration {
+    fn decode(input: &str) -> Result<Self> {
+        input
+            .trim()
+            .parse()
+            .map(Duration::from_nanos)
+            .map_err(|e| WakuError::Decode(format!("could not parse round trip time: {e}")))
+    }
+}
+
+fn ping_peer_request<'a>(
+    ctx: &'a WakuNodeContext,
+    address: &'a Multiaddr,
+    timeout: Option<Duration>,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let address = to_c_string(address.to_string(), WakuError::InvalidArgument)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let address_ptr = address.into_raw();
+
+        let out = waku_sys::waku_ping_peer(
+            ctx.obj_ptr,
+            address_ptr,
+            timeout_millis(timeout),
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(address_ptr));
+
+        out
+    })
+}
+
+/// Ping a peer using the libp2p ping protocol, returning the round trip time
+/// If `timeout` as milliseconds doesn't fit into a `i32` it is clamped to [`i32::MAX`]
+pub async fn waku_ping_peer(
+    ctx: &WakuNodeContext,
+    address: &Multiaddr,
+    timeout: Option<Duration>,
+) -> Result<Duration> {
+    let result = waku_request_async(
+        request_deadline(timeout),
+        ping_peer_request(ctx, address, timeout)?,
+    )
+    .await;
+
+    handle_json_response(result?)
+}
+
+/// Peers the node is currently connected to
+pub async fn waku_connected_peers(ctx: &WakuNodeContext) -> Result<Vec<PeerInfo>> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_get_connected_peers(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
+
+    handle_json_response(result?)
+}
+
+/// Ids of the peers stored in the node peerstore, connected or not
+pub async fn waku_peerstore_peers(ctx: &WakuNodeContext) -> Result<Vec<PeerId>> {
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        waku_sys::waku_get_peerids_from_peerstore(ctx.obj_ptr, cb, user_data)
+    })
+    .await;
+
+    handle_json_response(result?)
+}
+
+fn add_peer_request<'a>(
+    ctx: &'a WakuNodeContext,
+    address: &'a Multiaddr,
+    protocol: &'a str,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let address = to_c_string(address.to_string(), WakuError::InvalidArgument)?;
+    let protocol = to_c_string(protocol, WakuError::InvalidArgument)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let address_ptr = address.into_raw();
+        let protocol_ptr = protocol.into_raw();
+
+        let out = waku_sys::waku_add_peer(ctx.obj_ptr, address_ptr, protocol_ptr, cb, user_data);
+
+        drop(CString::from_raw(address_ptr));
+        drop(CString::from_raw(protocol_ptr));
+
+        out
+    })
+}
+
+/// Add a peer to the peerstore as serving `protocol`, without connecting to it
+pub async fn waku_add_peer(
+    ctx: &WakuNodeContext,
+    address: &Multiaddr,
+    protocol: &str,
+) -> Result<()> {
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        add_peer_request(ctx, address, protocol)?,
+    )
+    .await;
+
+    handle_no_response(result?)
+}
+
+fn disconnect_request<'a>(
+    ctx: &'a WakuNodeContext,
+    peer_id: &'a PeerId,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let peer_id = to_c_string(peer_id.to_string(), WakuError::InvalidPeerId)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let peer_id_ptr = peer_id.into_raw();
+
+        let out = waku_sys::waku_disconnect_peer_by_id(ctx.obj_ptr, peer_id_ptr, cb, user_data);
+
+        drop(CString::from_raw(peer_id_ptr));
+
+        out
+    })
+}
+
+/// Close the connections to a peer
+pub async fn waku_disconnect(ctx: &WakuNodeContext, peer_id: &PeerId) -> Result<()> {
+    let result =
+        waku_request_async(DEFAULT_REQUEST_DEADLINE, disconnect_request(ctx, peer_id)?).await;
+
+    handle_no_response(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{enr_addresses, ConnectionDirection, PeerAddress, PeerId, PeerInfo};
+    use crate::utils::WakuDecode;
+    use crate::WakuError;
+    use multiaddr::{Multiaddr, Protocol};
+    use secp256k1::SecretKey;
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+
+    #[test]
+    fn decode_peer_records() {
+        let s = "[{\"peerId\":\"16Uiu2HAmQE7FXQc6iZHdBzYfw3qCSDa9dLc1wsBJKoP4aZvztq2d\",\"addresses\":[\"/ip4/127.0.0.1/tcp/60010\"],\"protocols\":[\"/vac/waku/relay/2.0.0\"],\"direction\":\"Outbound\",\"shards\":[32]}]";
+        let peers = Vec::<PeerInfo>::decode(s).unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].addresses.len(), 1);
+        assert_eq!(peers[0].direction, ConnectionDirection::Outbound);
+        assert_eq!(peers[0].shards, vec![32]);
+    }
+
+    #[test]
+    fn decode_peer_ids() {
+        let s = "16Uiu2HAmQE7FXQc6iZHdBzYfw3qCSDa9dLc1wsBJKoP4aZvztq2d,16Uiu2HAm2M7xs7cLPc3jamawkEqbr7cUJX11uvY7LxQ6WFUdUKUT";
+        let peers = Vec::<PeerId>::decode(s).unwrap();
+        assert_eq!(peers.len(), 2);
+
+        assert!(Vec::<PeerId>::decode("").unwrap().is_empty());
+        assert!(matches!(
+            Vec::<PeerId>::decode("not-a-peer-id"),
+            Err(WakuError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn derive_peer_id_from_node_key() {
+        let secret_key =
+            SecretKey::from_str("05f381866cc21f6c1e2e80e07fa732008e36d942dce3206ad6dcd6793c98d609")
+                .unwrap();
+        let peer_id = PeerId::from_secret_key(&secret_key);
+        assert_eq!(
+            peer_id.to_string(),
+            "16Uiu2HAm34iMHeFfugScosRWkMArFBTiqaC1ai4Lga5zt6vG47qr"
+        );
+        assert_eq!(peer_id.to_string().parse::<PeerId>().unwrap(), peer_id);
+        assert_eq!(PeerId::from_bytes(&peer_id.to_bytes()).unwrap(), peer_id);
+    }
+
+    #[test]
+    fn peer_id_from_multiaddr() {
+        let address: Multiaddr =
+            "/ip4/127.0.0.1/tcp/60010/p2p/16Uiu2HAmQE7FXQc6iZHdBzYfw3qCSDa9dLc1wsBJKoP4aZvztq2d"
+                .parse()
+                .unwrap();
+        let peer_id = PeerId::from_multiaddr(&address).unwrap();
+        assert_eq!(
+            peer_id,
+            "16Uiu2HAmQE7FXQc6iZHdBzYfw3qCSDa9dLc1wsBJKoP4aZvztq2d"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            address.to_string(),
+            "/ip4/127.0.0.1/tcp/60010"
+                .parse::<Multiaddr>()
+                .unwrap()
+                .with(peer_id.into())
+                .to_string()
+        );
+
+        assert_eq!(PeerId::from_dialable(&address).unwrap(), peer_id);
+
+        let address = address.with(Protocol::P2pCircuit);
+        assert_eq!(PeerId::from_multiaddr(&address), Some(peer_id));
+        assert!(matches!(
+            PeerId::from_dialable(&address),
+            Err(WakuError::InvalidPeerId(_))
+        ));
+
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/60010".parse().unwrap();
+        assert!(PeerId::from_multiaddr(&address).is_none());
+        assert!(PeerId::from_dialable(&address).is_err());
+    }
+
+    #[test]
+    fn peer_id_from_enr() {
+        let secret_key = enr::secp256k1::SecretKey::from_str(
+            "05f381866cc21f6c1e2e80e07fa732008e36d942dce3206ad6dcd6793c98d609",
+        )
+        .unwrap();
+        let enr = enr::EnrBuilder::new("v4").build(&secret_key).unwrap();
+        assert_eq!(
+            PeerId::from(&enr).to_string(),
+            "16Uiu2HAm34iMHeFfugScosRWkMArFBTiqaC1ai4Lga5zt6vG47qr"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_peer_ids() {
+        assert!(matches!(
+            "16Uiu2HAm0OIl".parse::<PeerId>(),
+            Err(WakuError::InvalidPeerId(_))
+        ));
+        // sha2-256 multihash of a wrong size
+        assert!(PeerId::from_bytes(&[0x12, 0x02, 0x00, 0x00]).is_err());
+        assert!("".parse::<PeerId>().is_err());
+    }
+
+    #[test]
+    fn resolve_peer_addresses() {
+        let secret_key = enr::secp256k1::SecretKey::from_str(
+            "05f381866cc21f6c1e2e80e07fa732008e36d942dce3206ad6dcd6793c98d609",
+        )
+        .unwrap();
+        let enr = enr::EnrBuilder::new("v4")
+            .ip4(Ipv4Addr::LOCALHOST)
+            .tcp4(60010)
+            .build(&secret_key)
+            .unwrap();
+        let address: Multiaddr =
+            "/ip4/127.0.0.1/tcp/60010/p2p/16Uiu2HAm34iMHeFfugScosRWkMArFBTiqaC1ai4Lga5zt6vG47qr"
+                .parse()
+                .unwrap();
+
+        let peer = enr.to_base64().parse::<PeerAddress>().unwrap();
+        assert_eq!(peer, PeerAddress::from(&enr));
+        assert_eq!(enr_addresses(&enr).unwrap(), vec![address.clone()]);
+        let peer = address.to_string().parse::<PeerAddress>().unwrap();
+        assert_eq!(peer, PeerAddress::from(&address));
+
+        let peer_id = PeerId::from(&enr);
+        let peer = peer_id.to_string().parse::<PeerAddress>().unwrap();
+        assert_eq!(peer, PeerAddress::from(peer_id));
+
+        let enr = enr::EnrBuilder::new("v4").build(&secret_key).unwrap();
+        assert!(matches!(enr_addresses(&enr), Err(WakuError::InvalidEnr(_))));
+        assert!("enr:-invalid".parse::<PeerAddress>().is_err());
+    }
 }
@@ -2,39 +2,41 @@
 
 // std
 use std::ffi::CString;
+use std::sync::PoisonError;
 use std::time::Duration;
 // crates
 use libc::*;
+use waku_sys::WakuCallBack;
 // internal
-use crate::general::{Encoding, MessageHash, Result, WakuContentTopic, WakuMessage};
+use crate::general::{Encoding, MessageHash, Result, WakuContentTopic, WakuError, WakuMessage};
 use crate::node::events::WakuNodeContext;
-use crate::utils::{get_trampoline, handle_no_response, handle_response, LibwakuResponse};
+use crate::utils::{
+    handle_no_response, handle_response, request_deadline, timeout_millis, to_c_string,
+    waku_request_async, DEFAULT_REQUEST_DEADLINE,
+};
+
+/// Protocol id of the relay protocol
+pub const RELAY_PROTOCOL: &str = "/vac/waku/relay/2.0.0";
 
 /// Create a content topic according to [RFC 23](https://rfc.vac.dev/spec/23/)
 /// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_content_topicchar-applicationname-unsigned-int-applicationversion-char-contenttopicname-char-encoding)
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub fn waku_create_content_topic(
+pub async fn waku_create_content_topic(
     ctx: &WakuNodeContext,
     application_name: &str,
     application_version: u32,
     content_topic_name: &str,
     encoding: Encoding,
-) -> WakuContentTopic {
-    let application_name_ptr = CString::new(application_name)
-        .expect("Application name should always transform to CString")
-        .into_raw();
-    let content_topic_name_ptr = CString::new(content_topic_name)
-        .expect("Content topic should always transform to CString")
-        .into_raw();
-    let encoding_ptr = CString::new(encoding.to_string())
-        .expect("Encoding should always transform to CString")
-        .into_raw();
-
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
+) -> Result<WakuContentTopic> {
+    let application_name = to_c_string(application_name, WakuError::InvalidTopic)?;
+    let content_topic_name = to_c_string(content_topic_name, WakuError::InvalidTopic)?;
+    let encoding = to_c_string(encoding.to_string(), WakuError::InvalidTopic)?;
+
+    let result = waku_request_async(DEFAULT_REQUEST_DEADLINE, |cb, user_data| unsafe {
+        let application_name_ptr = application_name.into_raw();
+        let content_topic_name_ptr = content_topic_name.into_raw();
+        let encoding_ptr = encoding.into_raw();
+
         let out = waku_sys::waku_content_topic(
             ctx.obj_ptr,
             application_name_ptr,
@@ -42,7 +44,7 @@ pub fn waku_create_content_topic(
             content_topic_name_ptr,
             encoding_ptr,
             cb,
-            &mut closure as *mut _ as *mut c_void,
+            user_data,
         );
 
         drop(CString::from_raw(application_name_ptr));
@@ -50,109 +52,122 @@ pub fn waku_create_content_topic(
         drop(CString::from_raw(encoding_ptr));
 
         out
-    };
+    })
+    .await;
 
-    handle_response(code, result).expect("&str from result should always be extracted")
+    handle_response(result?)
 }
 
-/// Publish a message using Waku Relay
-/// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_relay_publishchar-messagejson-char-pubsubtopic-int-timeoutms)
-pub fn waku_relay_publish_message(
-    ctx: &WakuNodeContext,
-    message: &WakuMessage,
-    pubsub_topic: &str,
+fn relay_publish_request<'a>(
+    ctx: &'a WakuNodeContext,
+    message: &'a WakuMessage,
+    pubsub_topic: &'a str,
     timeout: Option<Duration>,
-) -> Result<MessageHash> {
-    let pubsub_topic = pubsub_topic.to_string();
-
-    let message_ptr = CString::new(
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let message = to_c_string(
         serde_json::to_string(&message)
             .expect("WakuMessages should always be able to success serializing"),
-    )
-    .expect("CString should build properly from the serialized waku message")
-    .into_raw();
-    let pubsub_topic_ptr = CString::new(pubsub_topic)
-        .expect("CString should build properly from pubsub topic")
-        .into_raw();
-
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
+        WakuError::InvalidArgument,
+    )?;
+    let pubsub_topic = to_c_string(pubsub_topic, WakuError::InvalidTopic)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let message_ptr = message.into_raw();
+        let pubsub_topic_ptr = pubsub_topic.into_raw();
+
         let out = waku_sys::waku_relay_publish(
             ctx.obj_ptr,
             pubsub_topic_ptr,
             message_ptr,
-            timeout
-                .map(|duration| {
-                    duration
-                        .as_millis()
-                        .try_into()
-                        .expect("Duration as milliseconds should fit in a u32")
-                })
-                .unwrap_or(0),
+            timeout_millis(timeout) as c_uint,
             cb,
-            &mut closure as *mut _ as *mut c_void,
+            user_data,
         );
 
         drop(CString::from_raw(message_ptr));
         drop(CString::from_raw(pubsub_topic_ptr));
 
         out
-    };
+    })
+}
 
-    handle_response(code, result)
+/// Publish a message using Waku Relay
+/// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_relay_publishchar-messagejson-char-pubsubtopic-int-timeoutms)
+pub async fn waku_relay_publish_message(
+    ctx: &WakuNodeContext,
+    message: &WakuMessage,
+    pubsub_topic: &str,
+    timeout: Option<Duration>,
+) -> Result<MessageHash> {
+    let result = waku_request_async(
+        request_deadline(timeout),
+        relay_publish_request(ctx, message, pubsub_topic, timeout)?,
+    )
+    .await;
+
+    handle_response(result?)
 }
 
-pub fn waku_relay_subscribe(ctx: &WakuNodeContext, pubsub_topic: &str) -> Result<()> {
-    let pubsub_topic = pubsub_topic.to_string();
-    let pubsub_topic_ptr = CString::new(pubsub_topic)
-        .expect("CString should build properly from pubsub topic")
-        .into_raw();
-
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        let out = waku_sys::waku_relay_subscribe(
-            ctx.obj_ptr,
-            pubsub_topic_ptr,
-            cb,
-            &mut closure as *mut _ as *mut c_void,
-        );
+fn relay_subscribe_request<'a>(
+    ctx: &'a WakuNodeContext,
+    pubsub_topic: &'a str,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let pubsub_topic = to_c_string(pubsub_topic, WakuError::InvalidTopic)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let pubsub_topic_ptr = pubsub_topic.into_raw();
+
+        let out = waku_sys::waku_relay_subscribe(ctx.obj_ptr, pubsub_topic_ptr, cb, user_data);
 
         drop(CString::from_raw(pubsub_topic_ptr));
 
         out
-    };
+    })
+}
 
-    handle_no_response(code, result)
+pub async fn waku_relay_subscribe(ctx: &WakuNodeContext, pubsub_topic: &str) -> Result<()> {
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        relay_subscribe_request(ctx, pubsub_topic)?,
+    )
+    .await;
+
+    handle_no_response(result?)?;
+    ctx.relay_subscriptions()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(pubsub_topic.to_string());
+    Ok(())
 }
 
-pub fn waku_relay_unsubscribe(ctx: &WakuNodeContext, pubsub_topic: &String) -> Result<()> {
-    let pubsub_topic = pubsub_topic.to_string();
-    let pubsub_topic_ptr = CString::new(pubsub_topic)
-        .expect("CString should build properly from pubsub topic")
-        .into_raw();
-
-    let mut result: LibwakuResponse = Default::default();
-    let result_cb = |r: LibwakuResponse| result = r;
-    let code = unsafe {
-        let mut closure = result_cb;
-        let cb = get_trampoline(&closure);
-        let out = waku_sys::waku_relay_subscribe(
-            ctx.obj_ptr,
-            pubsub_topic_ptr,
-            cb,
-            &mut closure as *mut _ as *mut c_void,
-        );
+fn relay_unsubscribe_request<'a>(
+    ctx: &'a WakuNodeContext,
+    pubsub_topic: &'a str,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let pubsub_topic = to_c_string(pubsub_topic, WakuError::InvalidTopic)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let pubsub_topic_ptr = pubsub_topic.into_raw();
+
+        let out = waku_sys::waku_relay_unsubscribe(ctx.obj_ptr, pubsub_topic_ptr, cb, user_data);
 
         drop(CString::from_raw(pubsub_topic_ptr));
 
         out
-    };
+    })
+}
 
-    handle_no_response(code, result)
+pub async fn waku_relay_unsubscribe(ctx: &WakuNodeContext, pubsub_topic: &str) -> Result<()> {
+    let result = waku_request_async(
+        DEFAULT_REQUEST_DEADLINE,
+        relay_unsubscribe_request(ctx, pubsub_topic)?,
+    )
+    .await;
+
+    handle_no_response(result?)?;
+    ctx.relay_subscriptions()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(pubsub_topic);
+    Ok(())
 }
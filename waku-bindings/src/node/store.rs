@@ -0,0 +1,309 @@
+//! Waku [store](https://rfc.vac.dev/spec/13/) protocol related methods
+
+// std
+use std::ffi::CString;
+use std::sync::Arc;
+use std::time::Duration;
+// crates
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use libc::*;
+use multiaddr::Multiaddr;
+use serde::{Deserialize, Serialize};
+use smart_default::SmartDefault;
+use waku_sys::WakuCallBack;
+// internal
+use crate::general::{MessageHash, Result, WakuContentTopic, WakuError, WakuMessage};
+use crate::node::events::WakuNodeContext;
+use crate::utils::{
+    handle_json_response, request_deadline, timeout_millis, to_c_string, waku_request_async,
+    WakuDecode,
+};
+
+/// Protocol id of the store v3 query protocol
+pub const STORE_QUERY_PROTOCOL: &str = "/vac/waku/store-query/3.0.0";
+
+/// Order in which a [`StoreQuery`] walks through the stored messages
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PagingDirection {
+    /// Newest messages first
+    #[default]
+    Backward,
+    /// Oldest messages first
+    Forward,
+}
+
+/// Store v3 query, built with setters and run with [`crate::WakuNodeHandle::store_query_stream`]
+/// As per the [specification](https://rfc.vac.dev/spec/13/#storequeryrequest)
+#[derive(Clone, Debug, SmartDefault, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreQuery {
+    request_id: String,
+    #[default(true)]
+    include_data: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pubsub_topic: Option<String>,
+    content_topics: Vec<WakuContentTopic>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_start: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_end: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    message_hashes: Vec<MessageHash>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pagination_cursor: Option<MessageHash>,
+    pagination_forward: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pagination_limit: Option<usize>,
+}
+
+impl StoreQuery {
+    /// Query returning the full messages, newest first
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only return messages published on `pubsub_topic`
+    pub fn pubsub_topic<S: Into<String>>(mut self, pubsub_topic: S) -> Self {
+        self.pubsub_topic = Some(pubsub_topic.into());
+        self
+    }
+
+    /// Only return messages matching one of `content_topics`, requires a pubsub topic
+    pub fn content_topics<I: IntoIterator<Item = WakuContentTopic>>(
+        mut self,
+        content_topics: I,
+    ) -> Self {
+        self.content_topics = content_topics.into_iter().collect();
+        self
+    }
+
+    /// Only return messages with a timestamp within `start..=end`, as Unix nanoseconds
+    pub fn time_range(mut self, start: Option<usize>, end: Option<usize>) -> Self {
+        self.time_start = start;
+        self.time_end = end;
+        self
+    }
+
+    /// Look up the given messages instead of filtering by topic
+    pub fn message_hashes<I: IntoIterator<Item = MessageHash>>(mut self, hashes: I) -> Self {
+        self.message_hashes = hashes.into_iter().collect();
+        self
+    }
+
+    /// Whether to return the messages or only their hashes. Default `true`
+    pub fn include_data(mut self, include_data: bool) -> Self {
+        self.include_data = include_data;
+        self
+    }
+
+    /// Amount of messages requested per page, the store node applies its own limit when unset
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.pagination_limit = Some(page_size);
+        self
+    }
+
+    /// Order of the returned messages. Default [`PagingDirection::Backward`]
+    pub fn direction(mut self, direction: PagingDirection) -> Self {
+        self.pagination_forward = direction == PagingDirection::Forward;
+        self
+    }
+
+    /// The same query, resuming after `cursor` under a fresh request id
+    fn next_page(&self, cursor: MessageHash) -> Self {
+        Self {
+            request_id: new_request_id(),
+            pagination_cursor: Some(cursor),
+            ..self.clone()
+        }
+    }
+}
+
+fn new_request_id() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+/// Message returned by a store query
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredMessage {
+    /// The message hash
+    pub message_hash: MessageHash,
+    /// The message, unless the query excluded data
+    #[serde(default)]
+    pub message: Option<WakuMessage>,
+    /// The pubsub topic the message was published on, unless the query excluded data
+    #[serde(default)]
+    pub pubsub_topic: Option<String>,
+}
+
+/// A page of results
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoreResponse {
+    status_code: u32,
+    #[serde(default)]
+    status_desc: String,
+    #[serde(default)]
+    messages: Vec<StoredMessage>,
+    #[serde(default)]
+    pagination_cursor: Option<MessageHash>,
+}
+
+impl WakuDecode for StoreResponse {
+    fn decode(input: &str) -> Result<Self> {
+        let response: StoreResponse = serde_json::from_str(input)?;
+        match response.status_code {
+            200 => Ok(response),
+            code => Err(WakuError::Libwaku(format!(
+                "store query failed with status {code}: {}",
+                response.status_desc
+            ))),
+        }
+    }
+}
+
+fn store_query_request<'a>(
+    ctx: &'a WakuNodeContext,
+    query: &'a StoreQuery,
+    peer: &'a Multiaddr,
+    timeout: Option<Duration>,
+) -> Result<impl FnOnce(WakuCallBack, *mut c_void) -> c_int + 'a> {
+    let query = to_c_string(
+        serde_json::to_string(query).expect("StoreQuery should always serialize properly"),
+        WakuError::InvalidArgument,
+    )?;
+    let peer = to_c_string(peer.to_string(), WakuError::InvalidArgument)?;
+
+    Ok(move |cb, user_data| unsafe {
+        let query_ptr = query.into_raw();
+        let peer_ptr = peer.into_raw();
+
+        let out = waku_sys::waku_store_query(
+            ctx.obj_ptr,
+            query_ptr,
+            peer_ptr,
+            timeout_millis(timeout),
+            cb,
+            user_data,
+        );
+
+        drop(CString::from_raw(query_ptr));
+        drop(CString::from_raw(peer_ptr));
+
+        out
+    })
+}
+
+/// Retrieve a single page of results of `query` from the store node `peer`
+/// As per the [specification](https://rfc.vac.dev/spec/36/#extern-char-waku_store_querychar-jsonquery-char-peerid-int-timeoutms)
+async fn waku_store_query(
+    ctx: &WakuNodeContext,
+    query: &StoreQuery,
+    peer: &Multiaddr,
+    timeout: Option<Duration>,
+) -> Result<StoreResponse> {
+    let result = waku_request_async(
+        request_deadline(timeout),
+        store_query_request(ctx, query, peer, timeout)?,
+    )
+    .await;
+
+    handle_json_response(result?)
+}
+
+/// Run `query` against the store node `peer`, fetching the following pages as the stream is consumed.
+/// `timeout` applies to each page request. The stream ends after the first error.
+pub fn waku_store_messages(
+    ctx: Arc<WakuNodeContext>,
+    query: StoreQuery,
+    peer: Multiaddr,
+    timeout: Option<Duration>,
+) -> BoxStream<'static, Result<StoredMessage>> {
+    let first_page = StoreQuery {
+        request_id: new_request_id(),
+        ..query
+    };
+
+    stream::try_unfold(Some(first_page), move |query| {
+        let ctx = Arc::clone(&ctx);
+        let peer = peer.clone();
+        async move {
+            let Some(query) = query else {
+                return Result::Ok(None);
+            };
+            let response = waku_store_query(&ctx, &query, &peer, timeout).await?;
+            let next = response
+                .pagination_cursor
+                .map(|cursor| query.next_page(cursor));
+            let messages = stream::iter(response.messages.into_iter().map(Result::Ok));
+            Ok(Some((messages, next)))
+        }
+    })
+    .try_flatten()
+    .boxed()
+}
+
+/// Run `query` against the store node `peer` like [`waku_store_messages`], collecting every page
+pub async fn waku_store_all_messages(
+    ctx: Arc<WakuNodeContext>,
+    query: StoreQuery,
+    peer: Multiaddr,
+    timeout: Option<Duration>,
+) -> Result<Vec<StoredMessage>> {
+    waku_store_messages(ctx, query, peer, timeout)
+        .try_collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PagingDirection, StoreQuery, StoreResponse};
+    use crate::utils::WakuDecode;
+    use crate::{Encoding, WakuContentTopic, WakuError};
+
+    #[test]
+    fn serialize_store_query() {
+        let query = StoreQuery::new()
+            .pubsub_topic("/waku/2/rs/16/32")
+            .content_topics([WakuContentTopic::new(
+                "toychat",
+                "2",
+                "huilong",
+                Encoding::Proto,
+            )])
+            .time_range(Some(1), None)
+            .page_size(20)
+            .direction(PagingDirection::Forward);
+
+        let json = serde_json::to_value(&query).unwrap();
+        assert_eq!(json["includeData"], true);
+        assert_eq!(json["pubsubTopic"], "/waku/2/rs/16/32");
+        assert_eq!(
+            json["contentTopics"],
+            serde_json::json!(["/toychat/2/huilong/proto"])
+        );
+        assert_eq!(json["timeStart"], 1);
+        assert!(json.get("timeEnd").is_none());
+        assert_eq!(json["paginationForward"], true);
+        assert_eq!(json["paginationLimit"], 20);
+
+        let next = query.next_page("0x01".to_string());
+        assert_eq!(next.pagination_cursor.as_deref(), Some("0x01"));
+        assert_ne!(next.request_id, query.request_id);
+    }
+
+    #[test]
+    fn decode_store_response() {
+        let s = "{\"requestId\":\"01\",\"statusCode\":200,\"statusDesc\":\"OK\",\"messages\":[{\"messageHash\":\"0x26ff3d7fbc950ea2158ce62fd76fd745eee0323c9eac23d0713843b0f04ea27c\",\"pubsubTopic\":\"/waku/2/rs/16/32\",\"message\":{\"payload\":\"SGkgZnJvbSDwn6aAIQ==\",\"contentTopic\":\"/toychat/2/huilong/proto\",\"timestamp\":1665580926660}}],\"paginationCursor\":\"0x26ff3d7fbc950ea2158ce62fd76fd745eee0323c9eac23d0713843b0f04ea27c\"}";
+        let response = StoreResponse::decode(s).unwrap();
+        assert_eq!(response.messages.len(), 1);
+        assert!(response.messages[0].message.is_some());
+        assert!(response.pagination_cursor.is_some());
+
+        let s = "{\"requestId\":\"01\",\"statusCode\":400,\"statusDesc\":\"BAD_REQUEST\"}";
+        assert!(matches!(
+            StoreResponse::decode(s),
+            Err(WakuError::Libwaku(msg)) if msg.contains("BAD_REQUEST")
+        ));
+    }
+}
@@ -0,0 +1,514 @@
+//! Waku fields of node records, as per the [WAKU2-ENR](https://rfc.vac.dev/spec/31/)
+//! and [relay sharding](https://rfc.vac.dev/spec/51/#discovery) specifications
+
+// std
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+// crates
+use enr::secp256k1::SecretKey;
+use multiaddr::{Multiaddr, Protocol};
+// internal
+use crate::general::{Result, WakuError};
+use crate::node::peers::PeerId;
+use crate::node::Enr;
+
+const CAPABILITIES_KEY: &str = "waku2";
+const MULTIADDRS_KEY: &str = "multiaddrs";
+const SHARDS_LIST_KEY: &str = "rs";
+const SHARDS_VECTOR_KEY: &str = "rsv";
+/// Shards a cluster holds, as many as the `rsv` bit vector has bits
+const MAX_SHARDS: u16 = 1024;
+/// From this many shards on, shards are recorded as a bit vector rather than a list, as nwaku does
+const SHARDS_VECTOR_THRESHOLD: usize = 64;
+
+/// Waku protocols a node advertises in the `waku2` field of its record
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WakuCapabilities {
+    /// Relays messages, see [`crate::RELAY_PROTOCOL`]
+    pub relay: bool,
+    /// Serves store queries, see [`crate::STORE_QUERY_PROTOCOL`]
+    pub store: bool,
+    /// Serves filter subscriptions, see [`crate::FILTER_SUBSCRIBE_PROTOCOL`]
+    pub filter: bool,
+    /// Serves lightpush requests, see [`crate::LIGHTPUSH_PROTOCOL`]
+    pub lightpush: bool,
+    /// Synchronizes its store with other store nodes
+    pub sync: bool,
+}
+
+impl WakuCapabilities {
+    /// Capabilities set in the `waku2` bitfield, relay being the least significant bit
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            relay: bits & 1 != 0,
+            store: bits & 1 << 1 != 0,
+            filter: bits & 1 << 2 != 0,
+            lightpush: bits & 1 << 3 != 0,
+            sync: bits & 1 << 4 != 0,
+        }
+    }
+
+    /// `waku2` bitfield of the capabilities
+    pub fn bits(&self) -> u8 {
+        [
+            self.relay,
+            self.store,
+            self.filter,
+            self.lightpush,
+            self.sync,
+        ]
+        .into_iter()
+        .enumerate()
+        .fold(0, |bits, (bit, enabled)| bits | (enabled as u8) << bit)
+    }
+}
+
+/// Shards of a cluster a node relays, from the `rs` or `rsv` field of its record
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RelayShards {
+    /// Cluster the shards belong to
+    pub cluster_id: u16,
+    /// Shard indices, below `1024`
+    pub shards: BTreeSet<u16>,
+}
+
+impl RelayShards {
+    /// `shards` of `cluster_id`
+    pub fn new<I: IntoIterator<Item = u16>>(cluster_id: u16, shards: I) -> Self {
+        Self {
+            cluster_id,
+            shards: shards.into_iter().collect(),
+        }
+    }
+
+    /// Whether `shard` of `cluster_id` is relayed
+    pub fn contains(&self, cluster_id: u16, shard: u16) -> bool {
+        self.cluster_id == cluster_id && self.shards.contains(&shard)
+    }
+
+    /// Shards as an `rs` list: cluster id, shard count, then each shard, all big endian
+    fn to_list(&self) -> Vec<u8> {
+        let mut list = self.cluster_id.to_be_bytes().to_vec();
+        list.push(self.shards.len() as u8);
+        list.extend(self.shards.iter().flat_map(|shard| shard.to_be_bytes()));
+        list
+    }
+
+    /// Shards as an `rsv` bit vector: cluster id, big endian, then a bit per shard,
+    /// shard `0` being the most significant bit of the first byte as in nwaku and js-waku
+    fn to_vector(&self) -> Vec<u8> {
+        let mut vector = self.cluster_id.to_be_bytes().to_vec();
+        vector.resize(2 + MAX_SHARDS as usize / 8, 0);
+        for shard in &self.shards {
+            vector[2 + *shard as usize / 8] |= 0x80 >> (shard % 8);
+        }
+        vector
+    }
+
+    fn from_list(list: &[u8]) -> Result<Self> {
+        match list {
+            [high, low, count, shards @ ..] if shards.len() == 2 * *count as usize => {
+                Ok(Self::new(
+                    u16::from_be_bytes([*high, *low]),
+                    shards
+                        .chunks_exact(2)
+                        .map(|shard| u16::from_be_bytes([shard[0], shard[1]])),
+                ))
+            }
+            _ => Err(WakuError::InvalidEnr(format!(
+                "malformed {SHARDS_LIST_KEY} field of {} bytes",
+                list.len()
+            ))),
+        }
+    }
+
+    fn from_vector(vector: &[u8]) -> Result<Self> {
+        if vector.len() != 2 + MAX_SHARDS as usize / 8 {
+            return Err(WakuError::InvalidEnr(format!(
+                "malformed {SHARDS_VECTOR_KEY} field of {} bytes",
+                vector.len()
+            )));
+        }
+        Ok(Self::new(
+            u16::from_be_bytes([vector[0], vector[1]]),
+            (0..MAX_SHARDS)
+                .filter(|shard| vector[2 + *shard as usize / 8] & 0x80 >> (shard % 8) != 0),
+        ))
+    }
+}
+
+/// View of the Waku fields of a node record: capabilities, addresses and relayed shards
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WakuEnr(Enr);
+
+impl WakuEnr {
+    /// Start building a record holding Waku fields
+    pub fn builder() -> WakuEnrBuilder {
+        WakuEnrBuilder::default()
+    }
+
+    /// Underlying record
+    pub fn enr(&self) -> &Enr {
+        &self.0
+    }
+
+    /// Take the underlying record
+    pub fn into_enr(self) -> Enr {
+        self.0
+    }
+
+    /// Libp2p peer id of the node
+    pub fn peer_id(&self) -> PeerId {
+        PeerId::from(&self.0)
+    }
+
+    /// Capabilities the node advertises, none if its record has no `waku2` field
+    pub fn capabilities(&self) -> WakuCapabilities {
+        match self.0.get(CAPABILITIES_KEY) {
+            Some([bits]) => WakuCapabilities::from_bits(*bits),
+            _ => WakuCapabilities::default(),
+        }
+    }
+
+    /// Addresses of the `multiaddrs` field, usually the ones not expressible through
+    /// the `ip`/`tcp` fields such as websocket or circuit relay addresses
+    pub fn multiaddrs(&self) -> Result<Vec<Multiaddr>> {
+        let Some(mut field) = self.0.get(MULTIADDRS_KEY) else {
+            return Ok(Vec::new());
+        };
+        let mut multiaddrs = Vec::new();
+        // Each address is prefixed with its length, as a big endian `u16`
+        while let [high, low, rest @ ..] = field {
+            let len = u16::from_be_bytes([*high, *low]) as usize;
+            if rest.len() < len {
+                break;
+            }
+            let (address, rest) = rest.split_at(len);
+            multiaddrs.push(
+                Multiaddr::try_from(address.to_vec())
+                    .map_err(|e| WakuError::InvalidEnr(format!("malformed multiaddr: {e}")))?,
+            );
+            field = rest;
+        }
+        if !field.is_empty() {
+            return Err(WakuError::InvalidEnr(format!(
+                "truncated {MULTIADDRS_KEY} field"
+            )));
+        }
+        Ok(multiaddrs)
+    }
+
+    /// Addresses the node can be dialed at, built from the `ip`/`tcp` fields followed by
+    /// the `multiaddrs` field, ending with `/p2p/<peer id>`.
+    /// A malformed `multiaddrs` field is ignored.
+    pub fn addresses(&self) -> Vec<Multiaddr> {
+        let peer_id = self.peer_id();
+        let tcp = [
+            self.0.ip4().map(IpAddr::V4).zip(self.0.tcp4()),
+            self.0.ip6().map(IpAddr::V6).zip(self.0.tcp6()),
+        ];
+        tcp.into_iter()
+            .flatten()
+            .map(|(ip, port)| Multiaddr::from(ip).with(Protocol::Tcp(port)))
+            .chain(self.multiaddrs().unwrap_or_default())
+            .map(|address| match PeerId::from_multiaddr(&address) {
+                Some(_) => address,
+                None => address.with(peer_id.into()),
+            })
+            .collect()
+    }
+
+    /// Shards the node relays, from its `rs` field or else its `rsv` field
+    pub fn relay_shards(&self) -> Result<Option<RelayShards>> {
+        if let Some(list) = self.0.get(SHARDS_LIST_KEY) {
+            return RelayShards::from_list(list).map(Some);
+        }
+        self.0
+            .get(SHARDS_VECTOR_KEY)
+            .map(RelayShards::from_vector)
+            .transpose()
+    }
+
+    /// Whether the node relays `shard` of `cluster_id`, false if its shards are malformed
+    pub fn relays_shard(&self, cluster_id: u16, shard: u16) -> bool {
+        matches!(self.relay_shards(), Ok(Some(shards)) if shards.contains(cluster_id, shard))
+    }
+}
+
+impl From<Enr> for WakuEnr {
+    fn from(enr: Enr) -> Self {
+        Self(enr)
+    }
+}
+
+impl From<WakuEnr> for Enr {
+    fn from(enr: WakuEnr) -> Self {
+        enr.0
+    }
+}
+
+impl FromStr for WakuEnr {
+    type Err = WakuError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Enr::from_str(s).map(Self).map_err(WakuError::InvalidEnr)
+    }
+}
+
+impl Display for WakuEnr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// Builder of [`WakuEnr`] records, signed by the node key once built
+#[derive(Clone, Debug, Default)]
+pub struct WakuEnrBuilder {
+    ip4: Option<Ipv4Addr>,
+    ip6: Option<Ipv6Addr>,
+    tcp: Option<u16>,
+    udp: Option<u16>,
+    capabilities: Option<WakuCapabilities>,
+    multiaddrs: Vec<Multiaddr>,
+    relay_shards: Option<RelayShards>,
+}
+
+impl WakuEnrBuilder {
+    /// IPv4 address the node is reachable at
+    pub fn ip4(mut self, ip: Ipv4Addr) -> Self {
+        self.ip4 = Some(ip);
+        self
+    }
+
+    /// IPv6 address the node is reachable at
+    pub fn ip6(mut self, ip: Ipv6Addr) -> Self {
+        self.ip6 = Some(ip);
+        self
+    }
+
+    /// Libp2p TCP port, recorded for every IP address given
+    pub fn tcp(mut self, port: u16) -> Self {
+        self.tcp = Some(port);
+        self
+    }
+
+    /// Discv5 UDP port, recorded for every IP address given
+    pub fn udp(mut self, port: u16) -> Self {
+        self.udp = Some(port);
+        self
+    }
+
+    /// Capabilities to record in the `waku2` field
+    pub fn capabilities(mut self, capabilities: WakuCapabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// Addresses to record in the `multiaddrs` field, without their `/p2p/<peer id>` suffix
+    pub fn multiaddrs<I: IntoIterator<Item = Multiaddr>>(mut self, multiaddrs: I) -> Self {
+        self.multiaddrs = multiaddrs.into_iter().collect();
+        self
+    }
+
+    /// Shards to record, as an `rs` list or as an `rsv` bit vector when there are many of them
+    pub fn relay_shards(mut self, relay_shards: RelayShards) -> Self {
+        self.relay_shards = Some(relay_shards);
+        self
+    }
+
+    /// Sign the record with `key`, the node key as understood by the [`enr`] crate
+    pub fn build(self, key: &SecretKey) -> Result<WakuEnr> {
+        let mut builder = enr::EnrBuilder::new("v4");
+        if let Some(ip) = self.ip4 {
+            builder.ip4(ip);
+            if let Some(port) = self.tcp {
+                builder.tcp4(port);
+            }
+            if let Some(port) = self.udp {
+                builder.udp4(port);
+            }
+        }
+        if let Some(ip) = self.ip6 {
+            builder.ip6(ip);
+            if let Some(port) = self.tcp {
+                builder.tcp6(port);
+            }
+            if let Some(port) = self.udp {
+                builder.udp6(port);
+            }
+        }
+        if let Some(capabilities) = self.capabilities {
+            builder.add_value(CAPABILITIES_KEY, &vec![capabilities.bits()]);
+        }
+        if !self.multiaddrs.is_empty() {
+            let mut field = Vec::new();
+            for address in &self.multiaddrs {
+                let address = address.to_vec();
+                let len = u16::try_from(address.len()).map_err(|_| {
+                    WakuError::InvalidEnr(format!("multiaddr of {} bytes", address.len()))
+                })?;
+                field.extend(len.to_be_bytes());
+                field.extend(address);
+            }
+            builder.add_value(MULTIADDRS_KEY, &field);
+        }
+        if let Some(relay_shards) = self.relay_shards {
+            if let Some(shard) = relay_shards
+                .shards
+                .iter()
+                .find(|shard| **shard >= MAX_SHARDS)
+            {
+                return Err(WakuError::InvalidEnr(format!(
+                    "shard {shard} is out of the {MAX_SHARDS} shards of a cluster"
+                )));
+            }
+            if relay_shards.shards.len() < SHARDS_VECTOR_THRESHOLD {
+                builder.add_value(SHARDS_LIST_KEY, &relay_shards.to_list());
+            } else {
+                builder.add_value(SHARDS_VECTOR_KEY, &relay_shards.to_vector());
+            }
+        }
+        builder
+            .build(key)
+            .map(WakuEnr)
+            .map_err(|e| WakuError::InvalidEnr(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RelayShards, WakuCapabilities, WakuEnr};
+    use crate::general::WakuError;
+    use enr::secp256k1::SecretKey;
+    use multiaddr::Multiaddr;
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+
+    fn key() -> SecretKey {
+        SecretKey::from_str("05f381866cc21f6c1e2e80e07fa732008e36d942dce3206ad6dcd6793c98d609")
+            .unwrap()
+    }
+
+    #[test]
+    fn capabilities_bitfield() {
+        let capabilities = WakuCapabilities {
+            relay: true,
+            store: true,
+            lightpush: true,
+            ..Default::default()
+        };
+        assert_eq!(capabilities.bits(), 0b1011);
+        assert_eq!(WakuCapabilities::from_bits(0b1011), capabilities);
+        assert_eq!(WakuCapabilities::from_bits(0b1_0100).bits(), 0b1_0100);
+    }
+
+    #[test]
+    fn decode_waku_fields() {
+        // Fields as nwaku records them: cluster 16 shards 32 and 64, one websocket address
+        let websocket = Multiaddr::from_str("/dns4/node-01.example.org/tcp/8000/wss").unwrap();
+        let mut multiaddrs = vec![0, websocket.to_vec().len() as u8];
+        multiaddrs.extend(websocket.to_vec());
+        let enr = enr::EnrBuilder::new("v4")
+            .ip4(Ipv4Addr::LOCALHOST)
+            .tcp4(60010)
+            .add_value("waku2", &vec![0b11_u8])
+            .add_value("multiaddrs", &multiaddrs)
+            .add_value("rs", &vec![0, 16, 2, 0, 32, 0, 64])
+            .build(&key())
+            .unwrap();
+        let enr = WakuEnr::from_str(&enr.to_base64()).unwrap();
+
+        assert!(enr.capabilities().relay && enr.capabilities().store);
+        assert!(!enr.capabilities().filter);
+        assert_eq!(enr.multiaddrs().unwrap(), vec![websocket]);
+        assert_eq!(
+            enr.relay_shards().unwrap(),
+            Some(RelayShards::new(16, [32, 64]))
+        );
+        assert_eq!(
+            enr.addresses()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "/ip4/127.0.0.1/tcp/60010/p2p/16Uiu2HAm34iMHeFfugScosRWkMArFBTiqaC1ai4Lga5zt6vG47qr",
+                "/dns4/node-01.example.org/tcp/8000/wss/p2p/16Uiu2HAm34iMHeFfugScosRWkMArFBTiqaC1ai4Lga5zt6vG47qr"
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_shards_vector() {
+        let mut vector = vec![0; 130];
+        vector[1] = 16;
+        vector[2] = 0b1000_0001;
+        vector[129] = 1;
+        let enr: WakuEnr = enr::EnrBuilder::new("v4")
+            .add_value("rsv", &vector)
+            .build(&key())
+            .unwrap()
+            .into();
+        assert_eq!(
+            enr.relay_shards().unwrap(),
+            Some(RelayShards::new(16, [0, 7, 1023]))
+        );
+
+        let enr: WakuEnr = enr::EnrBuilder::new("v4")
+            .add_value("rs", &vec![0, 16, 2, 0, 32])
+            .build(&key())
+            .unwrap()
+            .into();
+        assert!(matches!(enr.relay_shards(), Err(WakuError::InvalidEnr(_))));
+        assert!(!enr.relays_shard(16, 32));
+    }
+
+    #[test]
+    fn build_and_select_peers() {
+        let build = |store, shards: RelayShards| {
+            WakuEnr::builder()
+                .ip4(Ipv4Addr::LOCALHOST)
+                .tcp(60010)
+                .capabilities(WakuCapabilities {
+                    relay: true,
+                    store,
+                    ..Default::default()
+                })
+                .relay_shards(shards)
+                .build(&key())
+                .unwrap()
+        };
+        let peers = [
+            build(true, RelayShards::new(16, [32])),
+            build(false, RelayShards::new(16, [32])),
+            build(true, RelayShards::new(16, 0..100)),
+            build(true, RelayShards::new(1, [32])),
+        ];
+        assert!(peers[0].enr().get("rs").is_some());
+        assert!(peers[2].enr().get("rsv").is_some());
+
+        // Store capable peers in cluster 16 shard 32
+        let selected: Vec<_> = peers
+            .iter()
+            .filter(|peer| peer.capabilities().store && peer.relays_shard(16, 32))
+            .collect();
+        assert_eq!(selected, vec![&peers[0], &peers[2]]);
+        assert_eq!(peers[2].relay_shards().unwrap().unwrap().shards.len(), 100);
+
+        let websocket = Multiaddr::from_str("/ip4/127.0.0.1/tcp/8000/ws").unwrap();
+        let enr = WakuEnr::builder()
+            .multiaddrs([websocket.clone()])
+            .build(&key())
+            .unwrap();
+        assert_eq!(enr.multiaddrs().unwrap(), vec![websocket]);
+        assert_eq!(enr.addresses().len(), 1);
+
+        assert!(matches!(
+            WakuEnr::builder()
+                .relay_shards(RelayShards::new(16, [1024]))
+                .build(&key()),
+            Err(WakuError::InvalidEnr(_))
+        ));
+    }
+}
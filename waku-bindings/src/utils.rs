@@ -1,6 +1,15 @@
-use crate::general::Result;
+use crate::general::{Result, WakuError};
 use core::str::FromStr;
+use once_cell::sync::Lazy;
+use std::any::Any;
 use std::convert::TryFrom;
+use std::ffi::{c_void, CString};
+use std::future;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::task::{Poll, Waker};
+use std::time::Duration;
 use std::{slice, str};
 use waku_sys::WakuCallBack;
 use waku_sys::{RET_ERR, RET_MISSING_CALLBACK, RET_OK};
@@ -15,18 +24,15 @@ pub enum LibwakuResponse {
 }
 
 impl TryFrom<(u32, &str)> for LibwakuResponse {
-    type Error = String;
+    type Error = WakuError;
 
     fn try_from((ret_code, response): (u32, &str)) -> std::result::Result<Self, Self::Error> {
         let opt_value = Some(response.to_string()).filter(|s| !s.is_empty());
         match ret_code {
             RET_OK => Ok(LibwakuResponse::Success(opt_value)),
-            RET_ERR => Ok(LibwakuResponse::Failure(format!(
-                "waku error: {}",
-                response
-            ))),
+            RET_ERR => Ok(LibwakuResponse::Failure(response.to_string())),
             RET_MISSING_CALLBACK => Ok(LibwakuResponse::MissingCallback),
-            _ => Err(format!("undefined return code {}", ret_code)),
+            _ => Err(WakuError::UnknownReturnCode(ret_code)),
         }
     }
 }
@@ -40,76 +46,435 @@ pub fn decode<T: WakuDecode>(input: String) -> Result<T> {
     T::decode(input.as_str())
 }
 
-unsafe extern "C" fn trampoline<F>(
-    ret_code: ::std::os::raw::c_int,
-    data: *const ::std::os::raw::c_char,
-    data_len: usize,
-    user_data: *mut ::std::os::raw::c_void,
-) where
-    F: FnMut(LibwakuResponse),
-{
-    let closure = &mut *(user_data as *mut F);
+type CallbackErrorHook = Box<dyn Fn(WakuError) + Send + Sync>;
+
+static CALLBACK_ERROR_HOOK: Lazy<RwLock<CallbackErrorHook>> = Lazy::new(|| {
+    RwLock::new(Box::new(|err| {
+        eprintln!("waku callback error: {err}");
+    }))
+});
+
+/// Register the hook receiving errors raised inside callbacks invoked by libwaku,
+/// which cannot be returned to any caller: panics of user event handlers,
+/// events that libwaku delivered malformed and failures to shut down a dropped node.
+/// By default each of them is written to stderr as a `waku callback error: <error>` line,
+/// register a hook to route them to a logger instead.
+pub fn set_callback_error_hook<F: Fn(WakuError) + Send + Sync + 'static>(hook: F) {
+    *CALLBACK_ERROR_HOOK
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Box::new(hook);
+}
+
+pub(crate) fn report_callback_error(err: WakuError) {
+    let hook = CALLBACK_ERROR_HOOK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    // A panicking hook must not unwind into libwaku either
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| hook(err)));
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|msg| msg.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string())
+}
+
+/// Run the body of an `extern "C"` callback, stopping any panic from unwinding into libwaku
+pub(crate) fn guard_callback<F: FnOnce()>(body: F) {
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(body)) {
+        report_callback_error(WakuError::CallbackPanic(panic_message(payload)));
+    }
+}
 
+/// Build a [`LibwakuResponse`] out of the raw arguments libwaku hands to a callback
+///
+/// # Safety
+/// `data` must be null or point to `data_len` readable bytes
+pub(crate) unsafe fn read_response(
+    ret_code: c_int,
+    data: *const c_char,
+    data_len: usize,
+) -> Result<LibwakuResponse> {
     let response = if data.is_null() {
         ""
     } else {
-        str::from_utf8(slice::from_raw_parts(data as *mut u8, data_len))
-            .expect("could not retrieve response")
+        str::from_utf8(slice::from_raw_parts(data as *const u8, data_len))
+            .map_err(|e| WakuError::Decode(format!("could not retrieve response: {e}")))?
     };
 
-    let result = LibwakuResponse::try_from((ret_code as u32, response))
-        .expect("invalid response obtained from libwaku");
+    LibwakuResponse::try_from((ret_code as u32, response))
+}
+
+/// Convert `value` into a C string for libwaku,
+/// an interior NUL byte being reported as the error `invalid` builds
+pub(crate) fn to_c_string<T: Into<Vec<u8>>>(
+    value: T,
+    invalid: fn(String) -> WakuError,
+) -> Result<CString> {
+    CString::new(value).map_err(|e| {
+        invalid(format!(
+            "{:?} contains a NUL byte",
+            String::from_utf8_lossy(&e.into_vec())
+        ))
+    })
+}
+
+/// Deadline applied to libwaku requests that do not carry a timeout of their own
+pub const DEFAULT_REQUEST_DEADLINE: Duration = Duration::from_secs(60);
+
+/// Extra time given to libwaku to report back once a request's own timeout elapsed
+const REQUEST_DEADLINE_GRACE: Duration = Duration::from_secs(5);
+
+/// Timeout in milliseconds as expected by libwaku, clamped to [`i32::MAX`]. `0` means no timeout
+pub(crate) fn timeout_millis(timeout: Option<Duration>) -> c_int {
+    timeout
+        .map(|duration| duration.as_millis().try_into().unwrap_or(i32::MAX))
+        .unwrap_or(0)
+}
+
+/// Deadline to wait for a request that libwaku itself bounds with `timeout`
+pub fn request_deadline(timeout: Option<Duration>) -> Duration {
+    timeout
+        .filter(|timeout| !timeout.is_zero())
+        .map(|timeout| timeout + REQUEST_DEADLINE_GRACE)
+        .unwrap_or(DEFAULT_REQUEST_DEADLINE)
+}
+
+#[derive(Default)]
+struct RequestState {
+    response: Option<Result<LibwakuResponse>>,
+    waker: Option<Waker>,
+}
+
+/// Heap allocated slot completed by the callback of a single libwaku request.
+///
+/// The caller and libwaku each own a reference, so the slot stays valid whether the callback
+/// fires before the call returns, later on another thread, or after the caller gave up waiting.
+/// A callback that never fires leaks libwaku's reference, never a dangling pointer.
+#[derive(Default)]
+struct RequestCompletion {
+    state: Mutex<RequestState>,
+    completed: Condvar,
+}
+
+impl RequestCompletion {
+    /// Hand a reference to libwaku along with the callback that will release it
+    fn issue<F, R>(self: &Arc<Self>, call: F) -> R
+    where
+        F: FnOnce(WakuCallBack, *mut c_void) -> R,
+    {
+        let user_data = Arc::into_raw(self.clone()) as *mut c_void;
+        call(Some(request_callback), user_data)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RequestState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn complete(&self, response: Result<LibwakuResponse>) {
+        let mut state = self.lock();
+        state.response = Some(response);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.completed.notify_all();
+    }
+
+    /// Block until the callback fires or `deadline` elapses
+    fn wait(&self, deadline: Duration) -> Option<Result<LibwakuResponse>> {
+        let (mut state, _) = self
+            .completed
+            .wait_timeout_while(self.lock(), deadline, |state| state.response.is_none())
+            .unwrap_or_else(PoisonError::into_inner);
+        state.response.take()
+    }
+
+    /// Wait for the callback without blocking, until it fires or `deadline` elapses
+    async fn wait_async(self: Arc<Self>, deadline: Duration) -> Option<Result<LibwakuResponse>> {
+        let completion = future::poll_fn(|cx| {
+            let mut state = self.lock();
+            match state.response.take() {
+                Some(response) => Poll::Ready(response),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        });
+        tokio::time::timeout(deadline, completion).await.ok()
+    }
+}
+
+unsafe extern "C" fn request_callback(
+    ret_code: ::std::os::raw::c_int,
+    data: *const ::std::os::raw::c_char,
+    data_len: usize,
+    user_data: *mut ::std::os::raw::c_void,
+) {
+    guard_callback(|| {
+        // Take back the reference handed out in `RequestCompletion::issue`
+        let completion = Arc::from_raw(user_data as *const RequestCompletion);
+        completion.complete(read_response(ret_code, data, data_len));
+    });
+}
+
+/// Response to `call` that is already settled once it returned `code`:
+/// a request libwaku refused without accepting it is never answered later.
+fn refused_response(
+    completion: &Arc<RequestCompletion>,
+    code: c_int,
+) -> Option<Result<LibwakuResponse>> {
+    if code == RET_OK as c_int {
+        return None;
+    }
+    // libwaku reports most refusals through the callback before returning
+    if let Some(response) = completion.lock().response.take() {
+        return Some(response);
+    }
+    if code == RET_MISSING_CALLBACK as c_int {
+        // SAFETY: libwaku did not take the callback, the reference handed out in
+        // `RequestCompletion::issue` will never be released by it
+        unsafe { Arc::decrement_strong_count(Arc::as_ptr(completion)) };
+    }
+    Some(LibwakuResponse::try_from((
+        code as u32,
+        "request refused by libwaku",
+    )))
+}
+
+/// Issue a libwaku request through `call`, handing it the callback and `user_data` to use,
+/// and block until that callback delivers the response.
+/// A request refused with a code other than `RET_OK` is reported right away.
+/// [`LibwakuResponse::Undefined`] is returned if nothing arrives within `deadline`,
+/// an error if the callback arguments cannot be read.
+pub fn waku_request<F>(deadline: Duration, call: F) -> Result<LibwakuResponse>
+where
+    F: FnOnce(WakuCallBack, *mut c_void) -> c_int,
+{
+    let completion = Arc::new(RequestCompletion::default());
+    let code = completion.issue(call);
+    if let Some(response) = refused_response(&completion, code) {
+        return response;
+    }
 
-    closure(result);
+    completion
+        .wait(deadline)
+        .unwrap_or(Ok(LibwakuResponse::Undefined))
 }
 
-pub fn get_trampoline<F>(_closure: &F) -> WakuCallBack
+/// Async counterpart of [`waku_request`].
+/// Dropping the returned future cancels the wait, a response delivered afterwards is discarded.
+pub async fn waku_request_async<F>(deadline: Duration, call: F) -> Result<LibwakuResponse>
 where
-    F: FnMut(LibwakuResponse),
+    F: FnOnce(WakuCallBack, *mut c_void) -> c_int,
 {
-    Some(trampoline::<F>)
+    let completion = Arc::new(RequestCompletion::default());
+    let code = completion.issue(call);
+    if let Some(response) = refused_response(&completion, code) {
+        return response;
+    }
+
+    completion
+        .wait_async(deadline)
+        .await
+        .unwrap_or(Ok(LibwakuResponse::Undefined))
 }
 
-pub fn handle_no_response(code: i32, result: LibwakuResponse) -> Result<()> {
-    if result == LibwakuResponse::Undefined && code as u32 == RET_OK {
-        // Some functions will only execute the callback on error
-        return Ok(());
+/// Issue a libwaku request that only invokes its callback before `call` returns, if at all,
+/// as `waku_new` does when it fails. The callback reference is released once `call` returned,
+/// `None` meaning the callback was not invoked.
+pub fn waku_immediate_request<F, R>(call: F) -> (R, Option<Result<LibwakuResponse>>)
+where
+    F: FnOnce(WakuCallBack, *mut c_void) -> R,
+{
+    let completion = Arc::new(RequestCompletion::default());
+    let out = completion.issue(call);
+    let response = completion.lock().response.take();
+    if response.is_none() {
+        // SAFETY: the callback was not invoked and will not be anymore,
+        // so the reference handed out in `RequestCompletion::issue` is still owned by libwaku
+        unsafe { Arc::decrement_strong_count(Arc::as_ptr(&completion)) };
     }
+    (out, response)
+}
 
+/// Payload of a successful response, or the error a failed one stands for
+/// Payload of a request that must answer with one,
+/// [`LibwakuResponse::Undefined`] meaning the deadline expired before it did
+fn success_payload(result: LibwakuResponse) -> Result<Option<String>> {
     match result {
-        LibwakuResponse::Success(_) => Ok(()),
-        LibwakuResponse::Failure(v) => Err(v),
-        LibwakuResponse::MissingCallback => panic!("callback is required"),
-        LibwakuResponse::Undefined => panic!(
-            "undefined ffi state: code({}) was returned but callback was not executed",
-            code
-        ),
+        LibwakuResponse::Success(v) => Ok(v),
+        LibwakuResponse::Failure(v) => Err(WakuError::Libwaku(v)),
+        LibwakuResponse::MissingCallback => Err(WakuError::MissingCallback),
+        LibwakuResponse::Undefined => Err(WakuError::Timeout),
     }
 }
 
-pub fn handle_json_response<F: WakuDecode>(code: i32, result: LibwakuResponse) -> Result<F> {
+/// Some entry points only invoke their callback on error,
+/// so no response within the deadline counts as a success
+pub fn handle_no_response(result: LibwakuResponse) -> Result<()> {
     match result {
-        LibwakuResponse::Success(v) => decode(v.unwrap_or_default()),
-        LibwakuResponse::Failure(v) => Err(v),
-        LibwakuResponse::MissingCallback => panic!("callback is required"),
-        LibwakuResponse::Undefined => panic!(
-            "undefined ffi state: code({}) was returned but callback was not executed",
-            code
-        ),
+        LibwakuResponse::Success(_) | LibwakuResponse::Undefined => Ok(()),
+        LibwakuResponse::Failure(v) => Err(WakuError::Libwaku(v)),
+        LibwakuResponse::MissingCallback => Err(WakuError::MissingCallback),
     }
 }
 
-pub fn handle_response<F: FromStr>(code: i32, result: LibwakuResponse) -> Result<F> {
-    match result {
-        LibwakuResponse::Success(v) => v
-            .unwrap_or_default()
-            .parse()
-            .map_err(|_| "could not parse value".into()),
-        LibwakuResponse::Failure(v) => Err(v),
-        LibwakuResponse::MissingCallback => panic!("callback is required"),
-        LibwakuResponse::Undefined => panic!(
-            "undefined ffi state: code({}) was returned but callback was not executed",
-            code
-        ),
+pub fn handle_json_response<F: WakuDecode>(result: LibwakuResponse) -> Result<F> {
+    decode(success_payload(result)?.unwrap_or_default())
+}
+
+pub fn handle_response<F: FromStr>(result: LibwakuResponse) -> Result<F> {
+    success_payload(result)?
+        .unwrap_or_default()
+        .parse()
+        .map_err(|_| WakuError::Decode("could not parse value".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::thread;
+
+    /// Mimic libwaku answering `msg` from its own thread once `delay` elapsed
+    fn respond_later(
+        cb: WakuCallBack,
+        user_data: *mut c_void,
+        msg: &'static [u8],
+        delay: Duration,
+    ) -> c_int {
+        let user_data = user_data as usize;
+        thread::spawn(move || {
+            thread::sleep(delay);
+            unsafe {
+                cb.unwrap()(
+                    RET_OK as c_int,
+                    msg.as_ptr() as *const c_char,
+                    msg.len(),
+                    user_data as *mut c_void,
+                )
+            }
+        });
+        RET_OK as c_int
+    }
+
+    #[test]
+    fn request_completed_from_another_thread() {
+        let result = waku_request(Duration::from_secs(5), |cb, user_data| {
+            respond_later(cb, user_data, b"done", Duration::from_millis(50))
+        });
+        let result = result.unwrap();
+        assert_eq!(result, LibwakuResponse::Success(Some("done".to_string())));
+        assert!(handle_no_response(result).is_ok());
+    }
+
+    #[test]
+    fn request_without_callback_times_out() {
+        let result = waku_request(Duration::from_millis(50), |_, _| RET_OK as c_int);
+        let result = result.unwrap();
+        assert_eq!(result, LibwakuResponse::Undefined);
+        assert_eq!(handle_no_response(result), Ok(()));
+        assert_eq!(
+            handle_response::<usize>(LibwakuResponse::Undefined),
+            Err(WakuError::Timeout)
+        );
+    }
+
+    #[test]
+    fn refused_request_does_not_wait() {
+        let started = std::time::Instant::now();
+        let result = waku_request(Duration::from_secs(60), |_, _| {
+            RET_MISSING_CALLBACK as c_int
+        });
+        assert_eq!(result, Ok(LibwakuResponse::MissingCallback));
+
+        let result = waku_request(Duration::from_secs(60), |cb, user_data| {
+            let msg = b"invalid topic";
+            unsafe {
+                cb.unwrap()(
+                    RET_ERR as c_int,
+                    msg.as_ptr() as *const c_char,
+                    msg.len(),
+                    user_data,
+                )
+            };
+            RET_ERR as c_int
+        });
+        assert_eq!(
+            handle_no_response(result.unwrap()),
+            Err(WakuError::Libwaku("invalid topic".to_string()))
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn immediate_request_without_callback() {
+        let (out, response) = waku_immediate_request(|_, _| 42);
+        assert_eq!(out, 42);
+        assert!(response.is_none());
+    }
+
+    #[test]
+    fn nul_byte_is_rejected() {
+        assert_eq!(
+            to_c_string("/waku/2/\0/proto", WakuError::InvalidTopic),
+            Err(WakuError::InvalidTopic(
+                "\"/waku/2/\\0/proto\" contains a NUL byte".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn request_with_invalid_utf8_fails() {
+        let result = waku_request(Duration::from_secs(5), |cb, user_data| {
+            respond_later(cb, user_data, b"\xff\xfe", Duration::ZERO)
+        });
+        assert!(matches!(result, Err(WakuError::Decode(_))));
+    }
+
+    #[tokio::test]
+    async fn async_request_survives_late_callback() {
+        let result = waku_request_async(Duration::from_millis(10), |cb, user_data| {
+            respond_later(cb, user_data, b"done", Duration::from_millis(100))
+        })
+        .await;
+        assert_eq!(result, Ok(LibwakuResponse::Undefined));
+
+        // let the late callback complete the abandoned request
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let result = waku_request_async(Duration::from_secs(5), |cb, user_data| {
+            respond_later(cb, user_data, b"done", Duration::from_millis(10))
+        })
+        .await;
+        assert_eq!(
+            result,
+            Ok(LibwakuResponse::Success(Some("done".to_string())))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn callback_panic_is_reported() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let previous = std::mem::replace(
+            &mut *CALLBACK_ERROR_HOOK.write().unwrap(),
+            Box::new(move |err| {
+                let _ = sender.lock().unwrap().send(err);
+            }),
+        );
+
+        guard_callback(|| panic!("event handler failed"));
+        let reported = receiver.recv_timeout(Duration::from_secs(1));
+        *CALLBACK_ERROR_HOOK.write().unwrap() = previous;
+
+        assert_eq!(
+            reported,
+            Ok(WakuError::CallbackPanic("event handler failed".to_string()))
+        );
     }
 }
@@ -1,28 +1,31 @@
-use secp256k1::SecretKey;
+use secp256k1::{Secp256k1, SecretKey};
+use futures::StreamExt;
 use serial_test::serial;
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr};
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 use std::{collections::HashSet, str::from_utf8};
-use std::cell::OnceCell;
-use waku_bindings::LibwakuResponse;
 use tokio::time;
 use tokio::time::sleep;
 use waku_bindings::{
-    waku_destroy, waku_new, Encoding, Event, MessageHash, Running, WakuContentTopic, WakuMessage,
-    WakuNodeConfig, WakuNodeHandle,
+    waku_destroy, waku_new, Encoding, Event, LatencyTrackerConfig, MessageHash, MessageProtocol,
+    PeerAddress, PeerId, PeerLatencyTracker, RetentionPolicy, Running, StoreQuery,
+    WakuContentTopic, WakuError, WakuMessage, WakuNodeConfig, WakuNodeHandle, RELAY_PROTOCOL,
 };
 const ECHO_TIMEOUT: u64 = 1000;
+/// How long a published message may take to come back as an event
+const EVENT_TIMEOUT: Duration = Duration::from_secs(20);
 const ECHO_MESSAGE: &str = "Hi from 🦀!";
 const TEST_PUBSUBTOPIC: &str = "test";
 
-fn try_publish_relay_messages(
+async fn try_publish_relay_messages(
     node: &WakuNodeHandle<Running>,
     msg: &WakuMessage,
-) -> Result<HashSet<MessageHash>, String> {
-    let topic = TEST_PUBSUBTOPIC.to_string();
-    Ok(HashSet::from([
-        node.relay_publish_message(msg, &topic, None)?
-    ]))
+) -> Result<HashSet<MessageHash>, WakuError> {
+    Ok(HashSet::from([node
+        .relay_publish_message(msg, TEST_PUBSUBTOPIC, None)
+        .await?]))
 }
 
 async fn test_echo_messages(
@@ -30,43 +33,19 @@ async fn test_echo_messages(
     node2: &WakuNodeHandle<Running>,
     content: &'static str,
     content_topic: WakuContentTopic,
-) -> Result<(), String> {
-    // setting a naïve event handler to avoid appearing ERR messages in logs
-    node1.set_event_callback(&|_| {});
-
-    let rx_waku_message: OnceCell<WakuMessage> = OnceCell::new();
-
-    let closure = |response| {
-        if let LibwakuResponse::Success(v) = response {
-            let event: Event =
-                serde_json::from_str(v.unwrap().as_str()).expect("Parsing event to succeed");
-
-            match event {
-                Event::WakuMessage(evt) => {
-                    println!("WakuMessage event received: {:?}", evt.waku_message);
-                    // rx_waku_message = evt.waku_message; // Use the shared reference
-                    let _ = rx_waku_message.set(evt.waku_message);
-                }
-                Event::Unrecognized(err) => panic!("Unrecognized waku event: {:?}", err),
-                _ => panic!("event case not expected"),
-            };
-        }
-    };
-
-    println!("Before setting event callback");
-
-    node2.set_event_callback(&closure); // Set the event callback with the closure
+) -> Result<(), Box<dyn Error>> {
+    let mut events = node2.events();
 
     let topic = TEST_PUBSUBTOPIC;
-    node1.relay_subscribe(&topic).unwrap();
-    node2.relay_subscribe(&topic).unwrap();
+    node1.relay_subscribe(topic).await.unwrap();
+    node2.relay_subscribe(topic).await.unwrap();
 
     sleep(Duration::from_secs(3)).await;
 
     // Interconnect nodes
     println!("Connecting node1 to node2");
-    let addresses1 = node1.listen_addresses().unwrap();
-    node2.connect(&addresses1[0], None).unwrap();
+    let addresses1 = node1.listen_addresses().await.unwrap();
+    node2.connect(&addresses1[0], None, None).await.unwrap();
 
     // Wait for mesh to form
     sleep(Duration::from_secs(3)).await;
@@ -85,33 +64,32 @@ async fn test_echo_messages(
         Vec::new(),
         false,
     );
-    let _ids = try_publish_relay_messages(node1, &message).expect("send relay messages");
+    let _ids = try_publish_relay_messages(node1, &message)
+        .await
+        .expect("send relay messages");
 
     // Wait for the msg to arrive
-    for _ in 0..50 {
-        if let Some(msg) = rx_waku_message.get() {
-            println!("The waku message value is: {:?}", msg);
-            let payload = msg.payload.to_vec();
-            let payload_str = from_utf8(&payload).expect("should be valid message");
-            println!("payload: {:?}", payload_str);
-            if payload_str == ECHO_MESSAGE {
-                return Ok(())
+    time::timeout(EVENT_TIMEOUT, async {
+        while let Some(event) = events.next().await {
+            if let Event::WakuMessage(evt) = event? {
+                println!("WakuMessage event received: {:?}", evt.waku_message);
+                let payload = evt.waku_message.payload.to_vec();
+                let payload_str = from_utf8(&payload).expect("should be valid message");
+                println!("payload: {:?}", payload_str);
+                if payload_str == ECHO_MESSAGE {
+                    return Ok(());
+                }
             }
-        } else {
-            sleep(Duration::from_millis(100)).await;
         }
-    }
-
-    if let None = rx_waku_message.get() {
-        return Err("could not get waku message".to_string())
-    }
 
-    return Err("Unexpected test ending".to_string())
+        Err("could not get waku message".into())
+    })
+    .await?
 }
 
 #[tokio::test]
 #[serial]
-async fn default_echo() -> Result<(), String> {
+async fn default_echo() -> Result<(), WakuError> {
     println!("Test default_echo");
     let node1 = waku_new(Some(WakuNodeConfig {
         port: Some(60010),
@@ -122,8 +100,8 @@ async fn default_echo() -> Result<(), String> {
         ..Default::default()
     }))?;
 
-    let node1 = node1.start()?;
-    let node2 = node2.start()?;
+    let node1 = node1.start().await?;
+    let node2 = node2.start().await?;
 
     let content_topic = WakuContentTopic::new("toychat", "2", "huilong", Encoding::Proto);
 
@@ -133,22 +111,22 @@ async fn default_echo() -> Result<(), String> {
     // Send and receive messages. Waits until all messages received.
     let got_all = tokio::select! {
         _ = sleep => false,
-        _ = test_echo_messages(&node1, &node2, ECHO_MESSAGE, content_topic) => true,
+        echoed = test_echo_messages(&node1, &node2, ECHO_MESSAGE, content_topic) => echoed.is_ok(),
     };
 
     assert!(got_all);
 
-    let node1 = node1.stop()?;
-    let node2 = node2.stop()?;
-    waku_destroy(node1)?;
-    waku_destroy(node2)?;
+    let node1 = node1.stop().await?;
+    let node2 = node2.stop().await?;
+    waku_destroy(node1).await?;
+    waku_destroy(node2).await?;
 
     Ok(())
 }
 
-#[test]
+#[tokio::test]
 #[serial]
-fn node_restart() {
+async fn node_restart() {
     println!("Test node_restart");
     let config = WakuNodeConfig {
         node_key: Some(
@@ -157,12 +135,557 @@ fn node_restart() {
         ),
         ..Default::default()
     };
+    let peer_id: PeerId = "16Uiu2HAm34iMHeFfugScosRWkMArFBTiqaC1ai4Lga5zt6vG47qr"
+        .parse()
+        .unwrap();
 
     for _ in 0..3 {
         let node = waku_new(config.clone().into()).expect("default config should be valid");
 
-        let node = node.start().expect("node should start with valid config");
+        let node = node
+            .start()
+            .await
+            .expect("node should start with valid config");
+        let addresses = node.listen_addresses().await.expect("listen addresses");
+        assert!(addresses
+            .iter()
+            .all(|address| PeerId::from_multiaddr(address) == Some(peer_id)));
+
+        node.stop().await.expect("node should stop");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn node_lifecycle() -> Result<(), WakuError> {
+    let node = waku_new(Some(WakuNodeConfig {
+        port: Some(60010),
+        ..Default::default()
+    }))?;
+    let node = node.start().await?;
+
+    let version = node.version().await?;
+    assert!(!version.is_empty());
+    let addresses = node.listen_addresses().await?;
+    assert!(!addresses.is_empty());
+
+    node.relay_subscribe(TEST_PUBSUBTOPIC).await?;
+    node.relay_unsubscribe(TEST_PUBSUBTOPIC).await?;
+
+    let node = node.stop().await?;
+    waku_destroy(node).await
+}
+
+#[tokio::test]
+#[serial]
+async fn shared_handle_across_tasks() -> Result<(), WakuError> {
+    let node = waku_new(Some(WakuNodeConfig {
+        port: Some(60010),
+        ..Default::default()
+    }))?;
+    let node = node.start().await?;
+
+    let tasks: Vec<_> = (0..4)
+        .map(|_| {
+            let node = node.clone();
+            tokio::spawn(async move { node.version().await })
+        })
+        .collect();
+    for task in tasks {
+        assert!(!task.await.expect("task should not panic")?.is_empty());
+    }
+
+    let node = node.stop().await?;
+    waku_destroy(node).await
+}
+
+#[tokio::test]
+#[serial]
+async fn stopped_node_clones_are_not_running() -> Result<(), WakuError> {
+    let node = waku_new(Some(WakuNodeConfig {
+        port: Some(60010),
+        ..Default::default()
+    }))?;
+    let node = node.start().await?;
+    let clone = node.clone();
+
+    let node = node.stop().await?;
+    assert_eq!(clone.listen_addresses().await, Err(WakuError::NotRunning));
+    assert!(matches!(clone.stop().await, Err(WakuError::NotRunning)));
+
+    waku_destroy(node).await
+}
+
+#[tokio::test]
+#[serial]
+async fn dropped_node_releases_its_port() {
+    let config = WakuNodeConfig {
+        port: Some(60010),
+        ..Default::default()
+    };
+
+    // Dropping a running node stops and destroys it
+    let node = waku_new(Some(config.clone())).expect("default config should be valid");
+    let node = node
+        .start()
+        .await
+        .expect("node should start with valid config");
+    drop(node);
+
+    let node = waku_new(Some(config)).expect("default config should be valid");
+    let node = node.start().await.expect("port should have been released");
+    node.shutdown().await.expect("node should shut down");
+}
+
+#[tokio::test]
+#[serial]
+async fn filter_push() -> Result<(), Box<dyn Error>> {
+    let service = waku_new(Some(
+        WakuNodeConfig::builder().port(60010).filter(true).build()?,
+    ))?
+    .start()
+    .await?;
+    let client = waku_new(Some(
+        WakuNodeConfig::builder().port(60020).relay(false).build()?,
+    ))?
+    .start()
+    .await?;
+    let mut events = client.events();
+
+    service.relay_subscribe(TEST_PUBSUBTOPIC).await?;
+    let service_address = &service.listen_addresses().await?[0];
+    let content_topic = WakuContentTopic::new("toychat", "2", "huilong", Encoding::Proto);
+    client
+        .filter_subscribe(
+            service_address,
+            TEST_PUBSUBTOPIC,
+            std::slice::from_ref(&content_topic),
+        )
+        .await?;
+    client.filter_ping(service_address).await?;
+
+    let message = WakuMessage::new(
+        ECHO_MESSAGE,
+        content_topic.clone(),
+        1,
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis()
+            .try_into()?,
+        Vec::new(),
+        false,
+    );
+    service
+        .relay_publish_message(&message, TEST_PUBSUBTOPIC, None)
+        .await?;
+
+    let pushed = time::timeout(Duration::from_secs(ECHO_TIMEOUT), async {
+        while let Some(event) = events.next().await {
+            if let Ok(Event::WakuMessage(evt)) = event {
+                return Some(evt);
+            }
+        }
+        None
+    })
+    .await?
+    .ok_or("event stream ended")?;
+    assert_eq!(pushed.protocol, Some(MessageProtocol::Filter));
+    assert_eq!(from_utf8(&pushed.waku_message.payload)?, ECHO_MESSAGE);
+
+    client
+        .filter_unsubscribe(TEST_PUBSUBTOPIC, &[content_topic])
+        .await?;
+    client.filter_unsubscribe_all().await?;
+
+    client.shutdown().await?;
+    service.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn lightpush_publish() -> Result<(), Box<dyn Error>> {
+    let service = waku_new(Some(
+        WakuNodeConfig::builder()
+            .port(60010)
+            .lightpush(true)
+            .build()?,
+    ))?
+    .start()
+    .await?;
+    let client = waku_new(Some(
+        WakuNodeConfig::builder().port(60020).relay(false).build()?,
+    ))?
+    .start()
+    .await?;
+    let mut events = service.events();
+    service.relay_subscribe(TEST_PUBSUBTOPIC).await?;
+
+    let message = WakuMessage::new(
+        ECHO_MESSAGE,
+        WakuContentTopic::new("toychat", "2", "huilong", Encoding::Proto),
+        1,
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis()
+            .try_into()?,
+        Vec::new(),
+        false,
+    );
+    let service_address = &service.listen_addresses().await?[0];
+    let hash = client
+        .lightpush_publish(&message, TEST_PUBSUBTOPIC, Some(service_address))
+        .await?;
+
+    let relayed = time::timeout(Duration::from_secs(ECHO_TIMEOUT), async {
+        while let Some(event) = events.next().await {
+            if let Ok(Event::WakuMessage(evt)) = event {
+                return Some(evt);
+            }
+        }
+        None
+    })
+    .await?
+    .ok_or("event stream ended")?;
+    assert_eq!(relayed.message_hash, hash);
+
+    // The service node is now known, so it gets picked automatically
+    client
+        .lightpush_publish(&message, TEST_PUBSUBTOPIC, None)
+        .await?;
 
-        node.stop().expect("node should stop");
+    client.shutdown().await?;
+    service.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn store_relay_traffic() -> Result<(), Box<dyn Error>> {
+    let db_path = std::env::temp_dir().join(format!("waku-store-{}.sqlite3", std::process::id()));
+    let store = waku_new(Some(
+        WakuNodeConfig::builder()
+            .port(60010)
+            .store(true)
+            .store_message_db_url(format!("sqlite://{}", db_path.display()))
+            .store_message_retention_policy(RetentionPolicy::Capacity(1000))
+            .build()?,
+    ))?
+    .start()
+    .await?;
+    let client = waku_new(Some(WakuNodeConfig::builder().port(60020).build()?))?
+        .start()
+        .await?;
+
+    store.relay_subscribe(TEST_PUBSUBTOPIC).await?;
+    client.relay_subscribe(TEST_PUBSUBTOPIC).await?;
+    let store_address = &store.listen_addresses().await?[0];
+    client.connect(store_address, None, None).await?;
+
+    // Wait for mesh to form
+    sleep(Duration::from_secs(3)).await;
+
+    let content_topic = WakuContentTopic::new("toychat", "2", "huilong", Encoding::Proto);
+    let mut published = HashSet::new();
+    for i in 0..5 {
+        let message = WakuMessage::new(
+            format!("{ECHO_MESSAGE} {i}"),
+            content_topic.clone(),
+            1,
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_nanos()
+                .try_into()?,
+            Vec::new(),
+            false,
+        );
+        published.insert(
+            client
+                .relay_publish_message(&message, TEST_PUBSUBTOPIC, None)
+                .await?,
+        );
     }
+
+    // Wait for the store node to archive the messages
+    sleep(Duration::from_secs(2)).await;
+
+    // A page size smaller than the history makes the stream follow the cursor
+    let query = StoreQuery::new()
+        .pubsub_topic(TEST_PUBSUBTOPIC)
+        .content_topics([content_topic])
+        .page_size(2);
+    let mut stored = HashSet::new();
+    let mut messages =
+        client.store_query_stream(query, store_address, Some(Duration::from_secs(5)));
+    while let Some(message) = messages.next().await {
+        let message = message?;
+        assert!(message.message.is_some());
+        stored.insert(message.message_hash);
+    }
+    assert_eq!(stored, published);
+
+    client.shutdown().await?;
+    store.shutdown().await?;
+    let _ = std::fs::remove_file(db_path);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn node_identity() -> Result<(), Box<dyn Error>> {
+    let node_key =
+        SecretKey::from_str("05f381866cc21f6c1e2e80e07fa732008e36d942dce3206ad6dcd6793c98d609")?;
+    let node = waku_new(Some(
+        WakuNodeConfig::builder()
+            .port(60010)
+            .node_key(node_key)
+            .cluster_id(16)
+            .shards([32])
+            .build()?,
+    ))?
+    .start()
+    .await?;
+    node.relay_subscribe("/waku/2/rs/16/64").await?;
+
+    let peer_id = node.my_peer_id().await?;
+    assert_eq!(peer_id, PeerId::from_secret_key(&node_key));
+    assert_eq!(
+        node.my_enr().await?.public_key().serialize(),
+        node_key.public_key(&Secp256k1::new()).serialize()
+    );
+
+    let info = node.node_info().await?;
+    assert_eq!(info.peer_id, peer_id);
+    assert!(!info.version.is_empty());
+    assert!(!info.listen_addresses.is_empty());
+    assert_eq!(info.configured_protocols, vec![RELAY_PROTOCOL.to_string()]);
+    assert_eq!((info.cluster_id, info.shards), (Some(16), vec![32]));
+    assert_eq!(
+        info.relay_subscriptions,
+        vec![
+            "/waku/2/rs/16/32".to_string(),
+            "/waku/2/rs/16/64".to_string()
+        ]
+    );
+
+    node.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn ping_peers() -> Result<(), Box<dyn Error>> {
+    let node1 = waku_new(Some(WakuNodeConfig::builder().port(60010).build()?))?
+        .start()
+        .await?;
+    let node2 = waku_new(Some(WakuNodeConfig::builder().port(60020).build()?))?
+        .start()
+        .await?;
+    let address1 = node1.listen_addresses().await?[0].clone();
+
+    let rtt = node2.ping(&address1, Some(Duration::from_secs(5))).await?;
+    assert!(rtt < Duration::from_secs(5));
+
+    let tracker = PeerLatencyTracker::new(
+        node2.clone(),
+        LatencyTrackerConfig {
+            interval: Duration::from_millis(200),
+            ..Default::default()
+        },
+    );
+    tracker.track(address1.clone());
+    let task = tokio::spawn(tracker.clone().run());
+    sleep(Duration::from_secs(1)).await;
+    task.abort();
+
+    let latency = tracker.latency(&address1).expect("peer should be tracked");
+    assert!(latency.is_reachable());
+    assert_eq!(tracker.ranked()[0].0, address1);
+
+    drop(tracker);
+    node2.shutdown().await?;
+    node1.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn peer_management() -> Result<(), Box<dyn Error>> {
+    let node1 = waku_new(Some(WakuNodeConfig::builder().port(60010).build()?))?
+        .start()
+        .await?;
+    let node2 = waku_new(Some(WakuNodeConfig::builder().port(60020).build()?))?
+        .start()
+        .await?;
+
+    let address1 = &node1.listen_addresses().await?[0];
+    let peer_id = PeerId::from_multiaddr(address1).ok_or("no peer id in the listen address")?;
+    node2.add_peer(address1, RELAY_PROTOCOL).await?;
+    assert!(node2.peerstore_peers().await?.contains(&peer_id));
+    assert!(node2.connected_peers().await?.is_empty());
+
+    node2.connect(peer_id, None, None).await?;
+    sleep(Duration::from_secs(1)).await;
+    let connected = node2.connected_peers().await?;
+    assert_eq!(connected.len(), 1);
+    assert_eq!(connected[0].peer_id, peer_id);
+
+    node2.disconnect(&peer_id).await?;
+    sleep(Duration::from_secs(1)).await;
+    assert!(node2.connected_peers().await?.is_empty());
+
+    node2.shutdown().await?;
+    node1.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn connect_by_enr_and_peer_id() -> Result<(), Box<dyn Error>> {
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let node1 = waku_new(Some(
+        WakuNodeConfig::builder()
+            .host(localhost)
+            .port(60010)
+            .build()?,
+    ))?
+    .start()
+    .await?;
+    let node2 = waku_new(Some(
+        WakuNodeConfig::builder()
+            .host(localhost)
+            .port(60020)
+            .build()?,
+    ))?
+    .start()
+    .await?;
+
+    let enr1: PeerAddress = node1.my_enr().await?.to_base64().parse()?;
+    node2.connect(enr1, Some(&[RELAY_PROTOCOL]), None).await?;
+    sleep(Duration::from_secs(1)).await;
+    let peer_id1 = node1.my_peer_id().await?;
+    assert!(node2
+        .connected_peers()
+        .await?
+        .iter()
+        .any(|peer| peer.peer_id == peer_id1));
+
+    node2.disconnect(&peer_id1).await?;
+    sleep(Duration::from_secs(1)).await;
+    assert!(node2.connected_peers().await?.is_empty());
+
+    // node1 stays in the peerstore once disconnected
+    node2
+        .connect(peer_id1, Some(&[RELAY_PROTOCOL]), None)
+        .await?;
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(node2.connected_peers().await?.len(), 1);
+
+    // Without protocols, the peer id is dialed on relay
+    node2.disconnect(&peer_id1).await?;
+    sleep(Duration::from_secs(1)).await;
+    node2.connect(peer_id1, None, None).await?;
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(node2.connected_peers().await?.len(), 1);
+
+    node2.shutdown().await?;
+    node1.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn discv5_bootnode_rotation() -> Result<(), Box<dyn Error>> {
+    let discv5_config = |port, udp_port| {
+        WakuNodeConfig::builder()
+            .port(port)
+            .cluster_id(16)
+            .shards([32])
+            .discv5_discovery(true)
+            .discv5_udp_port(udp_port)
+            .build()
+    };
+    let node1 = waku_new(Some(discv5_config(60010, 9010)?))?.start().await?;
+    let node2 = waku_new(Some(discv5_config(60020, 9020)?))?.start().await?;
+
+    node2.stop_discv5().await?;
+    node2
+        .update_discv5_bootnodes(vec![node1.my_enr().await?])
+        .await?;
+    node2.start_discv5().await?;
+
+    let peer_id1 = node1.my_peer_id().await?;
+    let mut discovered = false;
+    for _ in 0..30 {
+        sleep(Duration::from_secs(1)).await;
+        if node2.peerstore_peers().await?.contains(&peer_id1) {
+            discovered = true;
+            break;
+        }
+    }
+    assert!(discovered, "node2 should discover node1 through discv5");
+
+    node2.shutdown().await?;
+    node1.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn peer_exchange_three_nodes() -> Result<(), Box<dyn Error>> {
+    // node2 serves peer exchange from the peers it finds through discv5
+    let node2 = waku_new(Some(
+        WakuNodeConfig::builder()
+            .port(60020)
+            .cluster_id(16)
+            .shards([32])
+            .discv5_discovery(true)
+            .discv5_udp_port(9020)
+            .peer_exchange(true)
+            .build()?,
+    ))?
+    .start()
+    .await?;
+    let node3 = waku_new(Some(
+        WakuNodeConfig::builder()
+            .port(60030)
+            .cluster_id(16)
+            .shards([32])
+            .discv5_discovery(true)
+            .discv5_udp_port(9030)
+            .discv5_bootstrap_nodes([node2.my_enr().await?])
+            .build()?,
+    ))?
+    .start()
+    .await?;
+    // node1 is a light client relying on peer exchange only
+    let node1 = waku_new(Some(
+        WakuNodeConfig::builder()
+            .port(60010)
+            .cluster_id(16)
+            .shards([32])
+            .build()?,
+    ))?
+    .start()
+    .await?;
+
+    let addresses2 = node2.listen_addresses().await?;
+    let peer_id3 = node3.my_peer_id().await?;
+    let mut received = Vec::new();
+    let mut learned = false;
+    for _ in 0..30 {
+        sleep(Duration::from_secs(1)).await;
+        received.extend(node1.peer_exchange_request(5, &addresses2[0]).await?);
+        if node1.peerstore_peers().await?.contains(&peer_id3) {
+            learned = true;
+            break;
+        }
+    }
+    assert!(learned, "node1 should learn node3 through node2");
+    assert!(received.iter().any(|enr| PeerId::from(enr) == peer_id3));
+
+    node1.shutdown().await?;
+    node3.shutdown().await?;
+    node2.shutdown().await?;
+    Ok(())
 }